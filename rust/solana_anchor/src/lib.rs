@@ -10,22 +10,83 @@ use {
         solana_program::{
             program::{invoke,invoke_signed},
             program_pack::Pack,
+            instruction::{Instruction,AccountMeta},
+            system_instruction,
+            hash::hashv,
         }      
     },
     metaplex_token_metadata::{
-        instruction::{create_metadata_accounts,create_master_edition,update_metadata_accounts,update_primary_sale_happened_via_token},
+        instruction::{update_metadata_accounts},
     },
     spl_token::state,
 };
 declare_id!("Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS");
 
-pub const POOL_SIZE : usize = 32+1+32;
-pub const CLIENT_SIZE : usize = 32+32+8+1+1;
-pub const METADATA_EXTENDED_SIZE : usize = 32+8+1;
+// Accounts are allocated with zeroed padding after their fields and start with
+// a version byte. Pool rewrites its variable-length fields in place, so its
+// fixed-size fields come first. Accounts from before versioning were allocated
+// at exactly their size and this runtime cannot realloc them, so
+// migrate_pool, migrate_client and migrate_metadata_extended copy them into
+// new accounts under the migrated pool and close the old ones. Listings from
+// before versioning stay where they are, since their sale manager PDA holds
+// the NFT and the proceeds; redeem_nft and withdraw_fund still accept them.
+pub const POOL_VERSION : u8 = 1;
+pub const CLIENT_VERSION : u8 = 1;
+pub const METADATA_EXTENDED_VERSION : u8 = 1;
+pub const SALE_MANAGER_VERSION : u8 = 1;
+pub const SALE_POT_VERSION : u8 = 1;
+pub const LEGACY_POOL_SIZE : usize = 32+1+32;
+pub const LEGACY_CLIENT_SIZE : usize = 32+32+8+1+1;
+pub const LEGACY_METADATA_EXTENDED_SIZE : usize = 32+8+1;
+pub const LEGACY_SALE_MANAGER_SIZE : usize = 32+32+32+32+32+8+1+1+1;
+pub const LEGACY_SALE_POT_SIZE : usize = 1+32+1+32+1+32+2+CREATOR_SIZE*MAX_CREATOR_NUM;
+
+pub const MAX_PAYMENT_MINTS : usize = 4;
+pub const MAX_MINT_PHASES : usize = 4;
+pub const MINT_PHASE_SIZE : usize = 1+8+8;
+pub const PHASE_WHITELIST : u8 = 0;
+pub const PHASE_PUBLIC : u8 = 1;
+pub const MAX_MINT_TIERS : usize = 4;
+pub const MINT_TIER_SIZE : usize = 8+8+8+8;
+pub const POOL_SIZE : usize = 1+32+1+32+32+1+32+8+1+8+2+32+8+32+1+8+8+1+1+8+8+1+32+32+32+32+32+8+8+8+4+32*MAX_PAYMENT_MINTS+4+MINT_PHASE_SIZE*MAX_MINT_PHASES+4+8*MAX_MINT_PHASES+4+MAX_NAME_LENGTH+4+MAX_URI_LENGTH+4+MINT_TIER_SIZE*MAX_MINT_TIERS;
+pub const POOL_PADDING : usize = 165;
+pub const CLIENT_SIZE : usize = 1+32+32+8+1+1+1+8;
+pub const CLIENT_PADDING : usize = 55;
+pub const METADATA_EXTENDED_SIZE : usize = 1+32+8+1+8+1+32+1;
+pub const METADATA_EXTENDED_PADDING : usize = 23;
+pub const MAX_FEE_BASIS_POINTS : u16 = 10000;
+pub const MAX_TIMELOCK_DELAY : i64 = 30*24*60*60;
+pub const MINT_RECORD_SIZE : usize = 32+32+8+1;
 pub const MAX_CREATOR_NUM : usize = 6;
 pub const CREATOR_SIZE : usize = 32+1+1;
-pub const MAX_SALE_MANAGER_SIZE : usize = 32+32+32+32+32+8+1+1+1;
-pub const SALE_POT_SIZE : usize = 1+32+1+32+1+32+2+CREATOR_SIZE*MAX_CREATOR_NUM;
+pub const MAX_METADATA_CREATORS : usize = 5;
+pub const MAX_NAME_LENGTH : usize = 32;
+pub const MAX_SYMBOL_LENGTH : usize = 10;
+pub const MAX_URI_LENGTH : usize = 200;
+pub const CONFIG_LINE_SIZE : usize = 4+MAX_NAME_LENGTH+4+MAX_URI_LENGTH;
+pub const CONFIG_LINES_SIZE : usize = 32+4+MAX_SYMBOL_LENGTH+2+4+CREATOR_SIZE*MAX_CREATOR_NUM+1+1+4+4+4;
+pub const MINT_BATCH_ACCOUNTS : usize = 5;
+pub const MAX_SALE_MANAGER_SIZE : usize = 1+32+32+32+32+32+8+1+1+1;
+pub const SALE_MANAGER_PADDING : usize = 64;
+pub const SALE_POT_SIZE : usize = 1+1+32+1+32+1+32+2+CREATOR_SIZE*MAX_CREATOR_NUM+1+32+2+8;
+pub const SALE_POT_PADDING : usize = 21;
+pub const POOL_REGISTRY_SIZE : usize = 32+8+8+1;
+// Sized for the largest variant, MintTiers.
+pub const POOL_CHANGE_SIZE : usize = 1+4+MINT_TIER_SIZE*MAX_MINT_TIERS;
+pub const QUEUED_CHANGE_SIZE : usize = 32+32+POOL_CHANGE_SIZE+8;
+pub const PROGRAM_CONFIG_SIZE : usize = 32+1+1;
+pub const POOL_ROLE_SIZE : usize = 32+32+1+1;
+pub const ROLE_WHITELIST_MANAGER : u8 = 1;
+pub const ROLE_PRICING_MANAGER : u8 = 2;
+pub const ROLE_SALE_CONTROLLER : u8 = 4;
+pub const ROLE_TREASURER : u8 = 8;
+pub const ROLE_ALL : u8 = ROLE_WHITELIST_MANAGER | ROLE_PRICING_MANAGER | ROLE_SALE_CONTROLLER | ROLE_TREASURER;
+pub const MAX_AUTHORITY_SIGNERS : usize = 10;
+pub const POOL_AUTHORITY_SIZE : usize = 32+4+32*MAX_AUTHORITY_SIGNERS+1+4+1+1;
+pub const MAX_PROPOSAL_ACCOUNTS : usize = 16;
+pub const MAX_PROPOSAL_DATA_SIZE : usize = 512;
+pub const PROPOSAL_ACCOUNT_SIZE : usize = 32+1+1;
+pub const AUTHORITY_PROPOSAL_SIZE : usize = 32+4+4+PROPOSAL_ACCOUNT_SIZE*MAX_PROPOSAL_ACCOUNTS+4+MAX_PROPOSAL_DATA_SIZE+4+MAX_AUTHORITY_SIGNERS+1;
 
 #[program]
 pub mod solana_anchor {
@@ -36,17 +97,337 @@ pub mod solana_anchor {
         ) -> ProgramResult {
         let pool = &mut ctx.accounts.pool;
         let sale_mint : state::Mint = state::Mint::unpack_from_slice(&ctx.accounts.sale_mint.data.borrow())?;
+        pool.version = POOL_VERSION;
         pool.owner = *ctx.accounts.owner.key;
         pool.presale_live = false;
         pool.sale_mint = *ctx.accounts.sale_mint.key;
+        pool.pending_owner = Pubkey::default();
+        pool.paused = false;
+        pool.pool_registry = Pubkey::default();
+        pool.registry_index = 0;
+        pool.bump = 0;
+        pool.active_listings = 0;
+        pool.payment_mints = vec![];
+        pool.fee_basis_points = 0;
+        pool.fee_treasury = Pubkey::default();
+        pool.timelock_delay = 0;
+        pool.merkle_root = [0; 32];
+        pool.phases = vec![];
+        pool.public_mint = false;
+        pool.wallet_limit = 0;
+        pool.mint_price = 0;
+        pool.mint_price_native = false;
+        pool.treasury_bump = treasury_bump(ctx.program_id, &pool.key());
+        pool.phase_prices = Vec::new();
+        pool.max_supply = 0;
+        pool.minted = 0;
+        pool.signer_bump = pool_signer_bump(ctx.program_id, &pool.key());
+        pool.collection_mint = Pubkey::default();
+        pool.edition_mint = Pubkey::default();
+        pool.hidden_name = String::new();
+        pool.hidden_uri = String::new();
+        pool.reveal_root = [0; 32];
+        pool.config_lines = Pubkey::default();
+        pool.legacy_pool = Pubkey::default();
+        pool.role_count = 0;
+        pool.config_lines_count = 0;
+        pool.treasury_balance = 0;
+        pool.tiers = vec![];
+        Ok(())
+    }
+
+    pub fn init_pool_registry(
+        ctx : Context<InitPoolRegistry>,
+        _bump : u8,
+        ) -> ProgramResult {
+        let pool_registry = &mut ctx.accounts.pool_registry;
+        pool_registry.owner = *ctx.accounts.owner.key;
+        pool_registry.count = 0;
+        pool_registry.active_pools = 0;
+        pool_registry.bump = _bump;
+        Ok(())
+    }
+
+    pub fn init_registered_pool(
+        ctx : Context<InitRegisteredPool>,
+        _bump : u8,
+        ) -> ProgramResult {
+        let pool_registry = &mut ctx.accounts.pool_registry;
+        let pool = &mut ctx.accounts.pool;
+        let _sale_mint : state::Mint = state::Mint::unpack_from_slice(&ctx.accounts.sale_mint.data.borrow())?;
+        pool.version = POOL_VERSION;
+        pool.owner = *ctx.accounts.owner.key;
+        pool.presale_live = false;
+        pool.sale_mint = *ctx.accounts.sale_mint.key;
+        pool.pending_owner = Pubkey::default();
+        pool.paused = false;
+        pool.pool_registry = pool_registry.key();
+        pool.registry_index = pool_registry.count;
+        pool.bump = _bump;
+        pool.active_listings = 0;
+        pool.payment_mints = vec![];
+        pool.fee_basis_points = 0;
+        pool.fee_treasury = Pubkey::default();
+        pool.timelock_delay = 0;
+        pool.merkle_root = [0; 32];
+        pool.phases = vec![];
+        pool.public_mint = false;
+        pool.wallet_limit = 0;
+        pool.mint_price = 0;
+        pool.mint_price_native = false;
+        pool.treasury_bump = treasury_bump(ctx.program_id, &pool.key());
+        pool.phase_prices = Vec::new();
+        pool.max_supply = 0;
+        pool.minted = 0;
+        pool.signer_bump = pool_signer_bump(ctx.program_id, &pool.key());
+        pool.collection_mint = Pubkey::default();
+        pool.edition_mint = Pubkey::default();
+        pool.hidden_name = String::new();
+        pool.hidden_uri = String::new();
+        pool.reveal_root = [0; 32];
+        pool.config_lines = Pubkey::default();
+        pool.legacy_pool = Pubkey::default();
+        pool.role_count = 0;
+        pool.config_lines_count = 0;
+        pool.treasury_balance = 0;
+        pool.tiers = vec![];
+        pool_registry.count = pool_registry.count + 1;
+        pool_registry.active_pools = pool_registry.active_pools + 1;
+        Ok(())
+    }
+
+    pub fn init_program_config(
+        ctx : Context<InitProgramConfig>,
+        _bump : u8,
+        ) -> ProgramResult {
+        assert_upgrade_authority(&ctx.accounts.program, &ctx.accounts.program_data, ctx.accounts.admin.key)?;
+        let config = &mut ctx.accounts.config;
+        config.admin = *ctx.accounts.admin.key;
+        config.paused = false;
+        config.bump = _bump;
+        Ok(())
+    }
+
+    pub fn set_program_paused(
+        ctx : Context<SetProgramPaused>,
+        _paused : bool,
+        ) -> ProgramResult {
+        let config = &mut ctx.accounts.config;
+        config.paused = _paused;
+        Ok(())
+    }
+
+    pub fn set_pool_paused(
+        ctx : Context<SetPoolPaused>,
+        _paused : bool,
+        ) -> ProgramResult {
+        assert_pool_role(ctx.program_id, &ctx.accounts.pool, ctx.accounts.owner.key, &ctx.accounts.pool_role, ROLE_SALE_CONTROLLER)?;
+        let pool = &mut ctx.accounts.pool;
+        pool.paused = _paused;
         Ok(())
     }
 
     pub fn set_authority(
         ctx : Context<SetAuthority>,
         ) -> ProgramResult {
+        assert_pool_version(&ctx.accounts.pool)?;
         let pool = &mut ctx.accounts.pool;
         pool.owner = *ctx.accounts.new_owner.key;
+        pool.pending_owner = Pubkey::default();
+        Ok(())
+    }
+
+    pub fn propose_pool_owner(
+        ctx : Context<ProposePoolOwner>,
+        ) -> ProgramResult {
+        assert_pool_version(&ctx.accounts.pool)?;
+        let pool = &mut ctx.accounts.pool;
+        if *ctx.accounts.new_owner.key == Pubkey::default() {
+            return Err(PoolError::InvalidPendingOwner.into());
+        }
+        pool.pending_owner = *ctx.accounts.new_owner.key;
+        Ok(())
+    }
+
+    pub fn accept_pool_owner(
+        ctx : Context<AcceptPoolOwner>,
+        ) -> ProgramResult {
+        assert_pool_version(&ctx.accounts.pool)?;
+        let pool = &mut ctx.accounts.pool;
+        if pool.pending_owner == Pubkey::default() || pool.pending_owner != *ctx.accounts.new_owner.key {
+            return Err(PoolError::InvalidPendingOwner.into());
+        }
+        pool.owner = pool.pending_owner;
+        pool.pending_owner = Pubkey::default();
+        Ok(())
+    }
+
+    pub fn cancel_pool_owner_proposal(
+        ctx : Context<CancelPoolOwnerProposal>,
+        ) -> ProgramResult {
+        assert_pool_version(&ctx.accounts.pool)?;
+        let pool = &mut ctx.accounts.pool;
+        if pool.pending_owner == Pubkey::default() {
+            return Err(PoolError::InvalidPendingOwner.into());
+        }
+        pool.pending_owner = Pubkey::default();
+        Ok(())
+    }
+
+    pub fn init_pool_authority(
+        ctx : Context<InitPoolAuthority>,
+        _bump : u8,
+        _signers : Vec<Pubkey>,
+        _threshold : u8,
+        ) -> ProgramResult {
+        assert_authority_signers(&_signers, _threshold)?;
+        let (authority_signer, signer_bump) = Pubkey::find_program_address(&[b"authority_signer".as_ref(), ctx.accounts.pool_authority.key().as_ref()], ctx.program_id);
+        if *ctx.accounts.authority_signer.key != authority_signer {
+            return Err(PoolError::InvalidAuthoritySigner.into());
+        }
+        let pool_authority = &mut ctx.accounts.pool_authority;
+        pool_authority.pool = ctx.accounts.pool.key();
+        pool_authority.signers = _signers;
+        pool_authority.threshold = _threshold;
+        pool_authority.seqno = 0;
+        pool_authority.signer_bump = signer_bump;
+        pool_authority.bump = _bump;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.owner = *ctx.accounts.authority_signer.key;
+        pool.pending_owner = Pubkey::default();
+        Ok(())
+    }
+
+    pub fn set_authority_signers(
+        ctx : Context<SetAuthoritySigners>,
+        _signers : Vec<Pubkey>,
+        _threshold : u8,
+        ) -> ProgramResult {
+        assert_authority_signers(&_signers, _threshold)?;
+        let pool_authority = &mut ctx.accounts.pool_authority;
+        pool_authority.signers = _signers;
+        pool_authority.threshold = _threshold;
+        pool_authority.seqno = pool_authority.seqno + 1;
+        Ok(())
+    }
+
+    pub fn create_authority_proposal(
+        ctx : Context<CreateAuthorityProposal>,
+        _accounts : Vec<ProposalAccount>,
+        _data : Vec<u8>,
+        ) -> ProgramResult {
+        let pool_authority = &ctx.accounts.pool_authority;
+        if _accounts.len() > MAX_PROPOSAL_ACCOUNTS || _data.len() > MAX_PROPOSAL_DATA_SIZE {
+            return Err(PoolError::InvalidProposal.into());
+        }
+        let index = pool_authority.signers.iter().position(|s| s == ctx.accounts.proposer.key)
+            .ok_or(PoolError::InvalidAuthoritySigner)?;
+        let proposal = &mut ctx.accounts.proposal;
+        proposal.pool_authority = pool_authority.key();
+        proposal.seqno = pool_authority.seqno;
+        proposal.accounts = _accounts;
+        proposal.data = _data;
+        proposal.approvals = vec![false; pool_authority.signers.len()];
+        proposal.approvals[index] = true;
+        proposal.executed = false;
+        Ok(())
+    }
+
+    pub fn approve_authority_proposal(
+        ctx : Context<ApproveAuthorityProposal>,
+        ) -> ProgramResult {
+        let pool_authority = &ctx.accounts.pool_authority;
+        let proposal = &mut ctx.accounts.proposal;
+        if proposal.seqno != pool_authority.seqno {
+            return Err(PoolError::StaleProposal.into());
+        }
+        if proposal.executed {
+            return Err(PoolError::ProposalAlreadyExecuted.into());
+        }
+        let index = pool_authority.signers.iter().position(|s| s == ctx.accounts.signer.key)
+            .ok_or(PoolError::InvalidAuthoritySigner)?;
+        proposal.approvals[index] = true;
+        Ok(())
+    }
+
+    pub fn execute_authority_proposal(
+        ctx : Context<ExecuteAuthorityProposal>,
+        ) -> ProgramResult {
+        let pool_authority = &ctx.accounts.pool_authority;
+        let proposal = &mut ctx.accounts.proposal;
+        if proposal.seqno != pool_authority.seqno {
+            return Err(PoolError::StaleProposal.into());
+        }
+        if proposal.executed {
+            return Err(PoolError::ProposalAlreadyExecuted.into());
+        }
+        let approvals = proposal.approvals.iter().filter(|a| **a).count();
+        if approvals < pool_authority.threshold as usize {
+            return Err(PoolError::NotEnoughApprovals.into());
+        }
+
+        let authority_signer_key = *ctx.accounts.authority_signer.key;
+        let instruction = Instruction{
+            program_id : *ctx.program_id,
+            accounts : proposal.accounts.iter().map(|a| AccountMeta{
+                pubkey : a.pubkey,
+                is_signer : a.is_signer || a.pubkey == authority_signer_key,
+                is_writable : a.is_writable,
+            }).collect(),
+            data : proposal.data.clone(),
+        };
+        let pool_authority_key = pool_authority.key();
+        let authority_signer_seeds = &[
+            b"authority_signer".as_ref(),
+            pool_authority_key.as_ref(),
+            &[pool_authority.signer_bump]
+        ];
+        invoke_signed(
+            &instruction,
+            ctx.remaining_accounts,
+            &[authority_signer_seeds]
+        )?;
+        proposal.executed = true;
+        Ok(())
+    }
+
+    pub fn set_pool_role(
+        ctx : Context<SetPoolRole>,
+        _bump : u8,
+        _roles : u8,
+        ) -> ProgramResult {
+        assert_pool_version(&ctx.accounts.pool)?;
+        if _roles & !ROLE_ALL != 0 {
+            return Err(PoolError::InvalidRoles.into());
+        }
+        let pool_role = &mut ctx.accounts.pool_role;
+        pool_role.pool = ctx.accounts.pool.key();
+        pool_role.member = *ctx.accounts.member.key;
+        pool_role.roles = _roles;
+        pool_role.bump = _bump;
+        ctx.accounts.pool.role_count = ctx.accounts.pool.role_count + 1;
+        Ok(())
+    }
+
+    pub fn close_pool_role(
+        ctx : Context<ClosePoolRole>,
+        ) -> ProgramResult {
+        assert_pool_version(&ctx.accounts.pool)?;
+        let pool = &mut ctx.accounts.pool;
+        pool.role_count = pool.role_count.saturating_sub(1);
+        Ok(())
+    }
+
+    pub fn update_pool_role(
+        ctx : Context<UpdatePoolRole>,
+        _roles : u8,
+        ) -> ProgramResult {
+        if _roles & !ROLE_ALL != 0 {
+            return Err(PoolError::InvalidRoles.into());
+        }
+        let pool_role = &mut ctx.accounts.pool_role;
+        pool_role.roles = _roles;
         Ok(())
     }
 
@@ -55,17 +436,23 @@ pub mod solana_anchor {
         _bump : u8,
         _amount : u64,
         _whitelisted : bool,
+        _tier : u8,
         ) -> ProgramResult {
         let pool = &ctx.accounts.pool;
+        assert_pool_role(ctx.program_id, pool, ctx.accounts.owner.key, &ctx.accounts.pool_role, ROLE_WHITELIST_MANAGER)?;
         if pool.presale_live {
             return Err(PoolError::PresaleLive.into());
         }
+        validate_client_tier(pool, _tier)?;
         let client = &mut ctx.accounts.client;
+        client.version = CLIENT_VERSION;
         client.owner = *ctx.accounts.bidder.key;
         client.pool = ctx.accounts.pool.key();
         client.amount = _amount;
         client.whitelisted = _whitelisted;
         client.bump = _bump;
+        client.tier = _tier;
+        client.minted = 0;
         Ok(())
     }
 
@@ -73,14 +460,79 @@ pub mod solana_anchor {
         ctx : Context<UpdateWhitelist>,
         _amount : u64,
         _whitelisted : bool,
+        _tier : u8,
         ) -> ProgramResult{
         let pool = &ctx.accounts.pool;
+        assert_pool_role(ctx.program_id, pool, ctx.accounts.owner.key, &ctx.accounts.pool_role, ROLE_WHITELIST_MANAGER)?;
         if pool.presale_live {
             return Err(PoolError::PresaleLive.into());
         }
+        validate_client_tier(pool, _tier)?;
         let client = &mut ctx.accounts.client;
         client.amount=_amount;
         client.whitelisted=_whitelisted;
+        client.tier=_tier;
+        Ok(())
+    }
+
+    pub fn set_whitelist_batch<'info>(
+        ctx : Context<'_, '_, '_, 'info, SetWhitelistBatch<'info>>,
+        _entries : Vec<WhitelistEntry>,
+        ) -> ProgramResult {
+        let pool = &ctx.accounts.pool;
+        assert_pool_role(ctx.program_id, pool, ctx.accounts.owner.key, &ctx.accounts.pool_role, ROLE_WHITELIST_MANAGER)?;
+        if pool.presale_live {
+            return Err(PoolError::PresaleLive.into());
+        }
+        if _entries.len() != ctx.remaining_accounts.len() {
+            return Err(PoolError::InvalidWhitelistBatch.into());
+        }
+        for (entry, client) in _entries.iter().zip(ctx.remaining_accounts.iter()) {
+            validate_client_tier(pool, entry.tier)?;
+            create_client(
+                ClientParams{
+                    program_id : ctx.program_id,
+                    pool : pool.key(),
+                    payer : ctx.accounts.owner.clone(),
+                    client : client.clone(),
+                    entry : entry,
+                    system_program : ctx.accounts.system_program.to_account_info().clone(),
+                    rent : ctx.accounts.rent.to_account_info().clone(),
+                }
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn update_whitelist_batch<'info>(
+        ctx : Context<'_, '_, '_, 'info, UpdateWhitelistBatch<'info>>,
+        _entries : Vec<WhitelistEntry>,
+        ) -> ProgramResult {
+        let pool = &ctx.accounts.pool;
+        assert_pool_role(ctx.program_id, pool, ctx.accounts.owner.key, &ctx.accounts.pool_role, ROLE_WHITELIST_MANAGER)?;
+        if pool.presale_live {
+            return Err(PoolError::PresaleLive.into());
+        }
+        if _entries.len() != ctx.remaining_accounts.len() {
+            return Err(PoolError::InvalidWhitelistBatch.into());
+        }
+        for (entry, client) in _entries.iter().zip(ctx.remaining_accounts.iter()) {
+            validate_client_tier(pool, entry.tier)?;
+            update_client(ctx.program_id, &pool.key(), client, entry)?;
+        }
+        Ok(())
+    }
+
+    pub fn set_merkle_root(
+        ctx : Context<SetMerkleRoot>,
+        _merkle_root : [u8; 32],
+        ) -> ProgramResult {
+        assert_pool_role(ctx.program_id, &ctx.accounts.pool, ctx.accounts.owner.key, &ctx.accounts.pool_role, ROLE_WHITELIST_MANAGER)?;
+        let pool = &mut ctx.accounts.pool;
+        if pool.presale_live {
+            return Err(PoolError::PresaleLive.into());
+        }
+        pool.merkle_root = _merkle_root;
         Ok(())
     }
 
@@ -88,725 +540,2878 @@ pub mod solana_anchor {
         ctx : Context<ControlPresaleLive>,
         _lived : bool,
         ) -> ProgramResult {
+        assert_pool_role(ctx.program_id, &ctx.accounts.pool, ctx.accounts.owner.key, &ctx.accounts.pool_role, ROLE_SALE_CONTROLLER)?;
         let pool = &mut ctx.accounts.pool;
         pool.presale_live = _lived;
         Ok(())
     }
 
-    pub fn mint_nft(
-        ctx : Context<MintNft>,
-        _bump : u8,
-        _data : Metadata,
+    pub fn set_mint_phases(
+        ctx : Context<SetMintPhases>,
+        _phases : Vec<MintPhase>,
         ) -> ProgramResult {
-        if !(&ctx.accounts.pool).presale_live {
-            return Err(PoolError::PresaleLive.into());
-        }
-        let client = &mut ctx.accounts.client;
-        let metadata_extended = &mut ctx.accounts.metadata_extended;
-        if client.pool != ctx.accounts.pool.key() {
-            return Err(PoolError::InvalidPoolAccount.into());
+        assert_pool_role(ctx.program_id, &ctx.accounts.pool, ctx.accounts.owner.key, &ctx.accounts.pool_role, ROLE_SALE_CONTROLLER)?;
+        if _phases.len() > MAX_MINT_PHASES {
+            return Err(PoolError::InvalidMintPhase.into());
         }
-        if !client.whitelisted {
-            return Err(PoolError::NotWhitelisted.into());
-        }
-        if client.amount == 0 {
-            return Err(PoolError::MintAmountIsZero.into());
+        for i in 0.._phases.len() {
+            let phase = &_phases[i];
+            if phase.kind > PHASE_PUBLIC || phase.start_ts >= phase.end_ts {
+                return Err(PoolError::InvalidMintPhase.into());
+            }
+            if i > 0 && phase.start_ts < _phases[i-1].end_ts {
+                return Err(PoolError::InvalidMintPhase.into());
+            }
         }
+        let pool = &mut ctx.accounts.pool;
+        pool.phases = _phases;
+        Ok(())
+    }
 
-        let mint : state::Mint = state::Mint::unpack_from_slice(&ctx.accounts.mint.data.borrow())?;
-        let token_account : state::Account = state::Account::unpack_from_slice(&ctx.accounts.token_account.data.borrow())?;
-        if mint.decimals != 0 {
-            return Err(PoolError::InvalidMintAccount.into());
+    pub fn set_mint_tiers(
+        ctx : Context<SetMintTiers>,
+        _tiers : Vec<MintTier>,
+        ) -> ProgramResult {
+        assert_pool_role(ctx.program_id, &ctx.accounts.pool, ctx.accounts.owner.key, &ctx.accounts.pool_role, ROLE_SALE_CONTROLLER)?;
+        if ctx.accounts.pool.timelock_delay != 0 {
+            return Err(PoolError::TimelockActive.into());
         }
-        if mint.supply != 0 {
-            return Err(PoolError::InvalidMintAccount.into());
+        validate_mint_tiers(&_tiers)?;
+        let pool = &mut ctx.accounts.pool;
+        pool.tiers = _tiers;
+        Ok(())
+    }
+
+    pub fn set_public_mint(
+        ctx : Context<SetPublicMint>,
+        _enabled : bool,
+        _wallet_limit : u64,
+        ) -> ProgramResult {
+        assert_pool_role(ctx.program_id, &ctx.accounts.pool, ctx.accounts.owner.key, &ctx.accounts.pool_role, ROLE_SALE_CONTROLLER)?;
+        let pool = &mut ctx.accounts.pool;
+        pool.public_mint = _enabled;
+        pool.wallet_limit = _wallet_limit;
+        Ok(())
+    }
+
+    pub fn init_config_lines(
+        ctx : Context<InitConfigLines>,
+        _symbol : String,
+        _seller_fee_basis_points : u16,
+        _creators : Vec<Creator>,
+        _is_mutable : bool,
+        _random : bool,
+        _max_lines : u32,
+        ) -> ProgramResult {
+        assert_pool_version(&ctx.accounts.pool)?;
+        if _symbol.len() > MAX_SYMBOL_LENGTH || _seller_fee_basis_points > MAX_FEE_BASIS_POINTS {
+            return Err(PoolError::InvalidConfigLines.into());
         }
-        if token_account.mint != *ctx.accounts.mint.key {
-            return Err(PoolError::InvalidTokenAccount.into());
+        let (pool_signer, _) = Pubkey::find_program_address(&[b"pool_signer".as_ref(), ctx.accounts.pool.key().as_ref()], ctx.program_id);
+        validate_creators(&_creators, &pool_signer)?;
+        let space = 8+CONFIG_LINES_SIZE+CONFIG_LINE_SIZE*(_max_lines as usize);
+        if ctx.accounts.config_lines.to_account_info().data_len() < space {
+            return Err(PoolError::InvalidConfigLines.into());
         }
+        let config_lines = &mut ctx.accounts.config_lines;
+        config_lines.pool = ctx.accounts.pool.key();
+        config_lines.symbol = _symbol;
+        config_lines.seller_fee_basis_points = _seller_fee_basis_points;
+        config_lines.creators = _creators.into_iter().map(|c| Creator{verified : false, ..c}).collect();
+        config_lines.is_mutable = _is_mutable;
+        config_lines.random = _random;
+        config_lines.max_lines = _max_lines;
+        config_lines.loaded = 0;
+        config_lines.redeemed = 0;
+        ctx.accounts.pool.config_lines = config_lines.key();
+        ctx.accounts.pool.config_lines_count = ctx.accounts.pool.config_lines_count + 1;
+        Ok(())
+    }
 
-        spl_token_mint_to(
-            TokenMintToParams{
-                mint : ctx.accounts.mint.clone(),
-                account : ctx.accounts.token_account.clone(),
-                owner : ctx.accounts.owner.clone(),
-                token_program : ctx.accounts.token_program.clone(),
-                amount : 1 as u64,
-            }
-        )?;
+    pub fn close_config_lines(
+        ctx : Context<CloseConfigLines>,
+        ) -> ProgramResult {
+        assert_pool_version(&ctx.accounts.pool)?;
+        let pool = &mut ctx.accounts.pool;
+        if pool.presale_live {
+            return Err(PoolError::PresaleLive.into());
+        }
+        if pool.config_lines == ctx.accounts.config_lines.key() {
+            pool.config_lines = Pubkey::default();
+        }
+        pool.config_lines_count = pool.config_lines_count.saturating_sub(1);
+        Ok(())
+    }
 
-        let mut creators : Vec<metaplex_token_metadata::state::Creator> = 
-            vec![metaplex_token_metadata::state::Creator{
-                address: ctx.accounts.pool.key(),
-                verified : true,
-                share : 0,
-            }];
-        creators.pop();
-        for c in _data.creators {
+    pub fn add_config_lines(
+        ctx : Context<AddConfigLines>,
+        _index : u32,
+        _lines : Vec<ConfigLine>,
+        ) -> ProgramResult {
+        assert_pool_version(&ctx.accounts.pool)?;
+        if ctx.accounts.pool.presale_live {
+            return Err(PoolError::PresaleLive.into());
+        }
+        let info = ctx.accounts.config_lines.to_account_info();
+        let config_lines = &mut ctx.accounts.config_lines;
+        if config_lines.redeemed != 0 || _index > config_lines.loaded {
+            return Err(PoolError::InvalidConfigLines.into());
+        }
+        let end = _index as usize + _lines.len();
+        if end > config_lines.max_lines as usize {
+            return Err(PoolError::InvalidConfigLines.into());
+        }
+        for (i, line) in _lines.iter().enumerate() {
+            write_config_line(&info, _index as usize + i, line)?;
+        }
+        if end as u32 > config_lines.loaded {
+            config_lines.loaded = end as u32;
+        }
+        Ok(())
+    }
 
-            creators.push(metaplex_token_metadata::state::Creator{
-                address : c.address,
-                verified : c.verified,
-                share : c.share,
-            });
+    pub fn set_collection(
+        ctx : Context<SetCollection>,
+        ) -> ProgramResult {
+        assert_pool_version(&ctx.accounts.pool)?;
+        // Prints take their collection from the master, so they could never be
+        // verified against the pool's collection.
+        if ctx.accounts.pool.edition_mint != Pubkey::default() {
+            return Err(PoolError::EditionModeConflict.into());
+        }
+        let pool_signer_key = *ctx.accounts.pool_signer.key;
+        let collection_metadata = load_nft_metadata(&ctx.accounts.collection_metadata, ctx.accounts.collection_mint.key)?;
+        if collection_metadata.update_authority == *ctx.accounts.owner.key {
+            invoke(
+                &update_metadata_accounts(
+                    *ctx.accounts.token_metadata_program.key,
+                    *ctx.accounts.collection_metadata.key,
+                    *ctx.accounts.owner.key,
+                    Some(pool_signer_key),
+                    None,
+                    None,
+                ),
+                &[
+                    ctx.accounts.token_metadata_program.clone(),
+                    ctx.accounts.collection_metadata.clone(),
+                    ctx.accounts.owner.clone(),
+                ]
+            )?;
+        } else if collection_metadata.update_authority != pool_signer_key {
+            return Err(PoolError::InvalidCollection.into());
         }
+        let pool = &mut ctx.accounts.pool;
+        pool.collection_mint = *ctx.accounts.collection_mint.key;
+        Ok(())
+    }
 
-        invoke(
-            &create_metadata_accounts(
+    // Hands the collection's update authority back from the pool signer to the
+    // owner. Later mints go out without a collection.
+    pub fn release_collection(
+        ctx : Context<ReleaseCollection>,
+        ) -> ProgramResult {
+        assert_pool_version(&ctx.accounts.pool)?;
+        let pool_key = ctx.accounts.pool.key();
+        let pool_signer_key = *ctx.accounts.pool_signer.key;
+        let collection_metadata = load_nft_metadata(&ctx.accounts.collection_metadata, ctx.accounts.collection_mint.key)?;
+        if collection_metadata.update_authority != pool_signer_key {
+            return Err(PoolError::InvalidCollection.into());
+        }
+        let pool_signer_seeds = &[
+            b"pool_signer".as_ref(),
+            pool_key.as_ref(),
+            &[ctx.accounts.pool.signer_bump]
+        ];
+        invoke_signed(
+            &update_metadata_accounts(
                 *ctx.accounts.token_metadata_program.key,
-                *ctx.accounts.metadata.key,
-                *ctx.accounts.mint.key,
-                *ctx.accounts.owner.key,
-                *ctx.accounts.owner.key,
-                *ctx.accounts.owner.key,
-                _data.name,
-                _data.symbol,
-                _data.uri,
-                Some(creators),
-                _data.seller_fee_basis_points,
-                true,
-                _data.is_mutable,
+                *ctx.accounts.collection_metadata.key,
+                pool_signer_key,
+                Some(*ctx.accounts.owner.key),
+                None,
+                None,
             ),
             &[
-                ctx.accounts.metadata.clone(),
-                ctx.accounts.mint.clone(),
-                ctx.accounts.owner.clone(),
-                ctx.accounts.owner.clone(),
-                ctx.accounts.owner.clone(),
                 ctx.accounts.token_metadata_program.clone(),
-                ctx.accounts.token_program.clone(),
-                ctx.accounts.system_program.to_account_info().clone(),
-                ctx.accounts.rent.to_account_info().clone(),
-            ]
+                ctx.accounts.collection_metadata.clone(),
+                ctx.accounts.pool_signer.clone(),
+            ],
+            &[pool_signer_seeds]
         )?;
+        let pool = &mut ctx.accounts.pool;
+        if pool.collection_mint == *ctx.accounts.collection_mint.key {
+            pool.collection_mint = Pubkey::default();
+        }
+        Ok(())
+    }
 
-        invoke(
-            &create_master_edition(
+    pub fn set_hidden_mode(
+        ctx : Context<SetHiddenMode>,
+        _name : String,
+        _uri : String,
+        _reveal_root : [u8; 32],
+        _leaf_count : u64,
+        ) -> ProgramResult {
+        assert_pool_version(&ctx.accounts.pool)?;
+        // reveal rewrites metadata, so it must stay mutable, and every mint
+        // needs a committed leaf.
+        if ctx.accounts.config_lines.key() != ctx.accounts.pool.config_lines || !ctx.accounts.config_lines.is_mutable {
+            return Err(PoolError::InvalidConfigLines.into());
+        }
+        let pool = &mut ctx.accounts.pool;
+        if pool.max_supply == 0 || pool.max_supply > _leaf_count {
+            return Err(PoolError::InvalidMaxSupply.into());
+        }
+        if pool.reveal_root != [0; 32] {
+            return Err(PoolError::RevealCommitted.into());
+        }
+        if pool.edition_mint != Pubkey::default() {
+            return Err(PoolError::EditionModeConflict.into());
+        }
+        if _name.len() > MAX_NAME_LENGTH || _uri.len() > MAX_URI_LENGTH || _reveal_root == [0; 32] {
+            return Err(PoolError::InvalidHiddenMetadata.into());
+        }
+        pool.hidden_name = _name;
+        pool.hidden_uri = _uri;
+        pool.reveal_root = _reveal_root;
+        Ok(())
+    }
+
+    pub fn reveal(
+        ctx : Context<Reveal>,
+        _line : ConfigLine,
+        _proof : Vec<[u8; 32]>,
+        ) -> ProgramResult {
+        assert_pool_version(&ctx.accounts.pool)?;
+        let pool = &ctx.accounts.pool;
+        let metadata_extended = &mut ctx.accounts.metadata_extended;
+        if !metadata_extended.hidden {
+            return Err(PoolError::AlreadyRevealed.into());
+        }
+        let leaf = hashv(&[&metadata_extended.mint_index.to_le_bytes(), &_line.try_to_vec()?]).to_bytes();
+        if !verify_merkle_proof(&_proof, &pool.reveal_root, leaf) {
+            return Err(PoolError::InvalidMerkleProof.into());
+        }
+        let metadata = load_nft_metadata(&ctx.accounts.metadata, ctx.accounts.mint.key)?;
+        let holder_token : state::Account = state::Account::unpack_from_slice(&ctx.accounts.holder_token.data.borrow())?;
+        if holder_token.mint != *ctx.accounts.mint.key || holder_token.amount != 1 {
+            return Err(PoolError::InvalidTokenAccount.into());
+        }
+
+        let pool_key = pool.key();
+        let pool_signer_seeds = &[
+            b"pool_signer".as_ref(),
+            pool_key.as_ref(),
+            &[pool.signer_bump]
+        ];
+        invoke_signed(
+            &update_metadata_accounts(
                 *ctx.accounts.token_metadata_program.key,
-                *ctx.accounts.master_edition.key,
-                *ctx.accounts.mint.key,
-                *ctx.accounts.owner.key,
-                *ctx.accounts.owner.key,
                 *ctx.accounts.metadata.key,
-                *ctx.accounts.owner.key,
+                *ctx.accounts.pool_signer.key,
+                Some(holder_token.owner),
+                Some(metaplex_token_metadata::state::Data{
+                    name : _line.name,
+                    symbol : metadata.data.symbol,
+                    uri : _line.uri,
+                    seller_fee_basis_points : metadata.data.seller_fee_basis_points,
+                    creators : metadata.data.creators,
+                }),
                 None,
             ),
             &[
-                ctx.accounts.master_edition.clone(),
-                ctx.accounts.mint.clone(),
-                ctx.accounts.owner.clone(),
-                ctx.accounts.owner.clone(),
-                ctx.accounts.owner.clone(),
+                ctx.accounts.token_metadata_program.clone(),
                 ctx.accounts.metadata.clone(),
-                ctx.accounts.token_program.clone(),
-                ctx.accounts.system_program.to_account_info().clone(),
-                ctx.accounts.rent.to_account_info().clone(),
-            ]
+                ctx.accounts.pool_signer.clone(),
+            ],
+            &[pool_signer_seeds]
         )?;
-
-        client.amount = client.amount-1;
-        metadata_extended.pool = ctx.accounts.pool.key();
-        metadata_extended.max_price = 0;
-        metadata_extended.bump = _bump;
+        metadata_extended.hidden = false;
         Ok(())
     }
 
-    pub fn set_max_price(
-        ctx : Context<SetMaxPrice>,
-        _max_price : u64,
+    // Edition mode prints every mint from one master, so it cannot be combined
+    // with a pool collection or hidden mode.
+    pub fn set_edition_mint(
+        ctx : Context<SetEditionMint>,
         ) -> ProgramResult {
-        let metadata_extended = &mut ctx.accounts.metadata_extended;
-        if metadata_extended.pool != ctx.accounts.pool.key() {
-            return Err(PoolError::InvalidPoolAccount.into());
+        assert_pool_role(ctx.program_id, &ctx.accounts.pool, ctx.accounts.owner.key, &ctx.accounts.pool_role, ROLE_SALE_CONTROLLER)?;
+        let pool = &ctx.accounts.pool;
+        if pool.collection_mint != Pubkey::default() || pool.reveal_root != [0; 32] {
+            return Err(PoolError::EditionModeConflict.into());
         }
-        metadata_extended.max_price=_max_price;
+        let edition_token : state::Account = state::Account::unpack_from_slice(&ctx.accounts.edition_token.data.borrow())?;
+        if edition_token.mint != *ctx.accounts.edition_mint.key || edition_token.owner != *ctx.accounts.pool_signer.key || edition_token.amount != 1 {
+            return Err(PoolError::InvalidEdition.into());
+        }
+        // Prints copy the master's creators, so they only pass as pool NFTs if
+        // the master does.
+        let edition_metadata = load_nft_metadata(&ctx.accounts.edition_metadata, ctx.accounts.edition_mint.key)?;
+        if !is_pool_nft(&edition_metadata, ctx.accounts.pool_signer.key) {
+            return Err(PoolError::NotPoolNft.into());
+        }
+        let pool = &mut ctx.accounts.pool;
+        pool.edition_mint = *ctx.accounts.edition_mint.key;
         Ok(())
     }
 
-    pub fn init_sale_manager(
-        ctx : Context<InitSaleManager>,
-        _bump : u8
+    // The pool signer only holds the master to print from it, so leaving
+    // edition mode hands it back to the pool owner.
+    pub fn clear_edition_mint(
+        ctx : Context<ClearEditionMint>,
         ) -> ProgramResult {
-        let sale_manager = &mut ctx.accounts.sale_manager;
-        sale_manager.bump = _bump;
-        sale_manager.pool = ctx.accounts.pool.key();
-        sale_manager.nft_mint = *ctx.accounts.nft_mint.key;
-        sale_manager.sale_state = 0;
+        assert_pool_role(ctx.program_id, &ctx.accounts.pool, ctx.accounts.owner.key, &ctx.accounts.pool_role, ROLE_SALE_CONTROLLER)?;
+        let pool = &ctx.accounts.pool;
+        let edition_token : state::Account = state::Account::unpack_from_slice(&ctx.accounts.edition_token.data.borrow())?;
+        if pool.edition_mint == Pubkey::default() || edition_token.mint != pool.edition_mint || edition_token.owner != *ctx.accounts.pool_signer.key {
+            return Err(PoolError::InvalidEdition.into());
+        }
+        let owner_token : state::Account = state::Account::unpack_from_slice(&ctx.accounts.owner_token.data.borrow())?;
+        if owner_token.mint != pool.edition_mint || owner_token.owner != pool.owner {
+            return Err(PoolError::InvalidTokenAccount.into());
+        }
+        let pool_key = pool.key();
+        let pool_signer_seeds = &[
+            b"pool_signer".as_ref(),
+            pool_key.as_ref(),
+            &[pool.signer_bump]
+        ];
+        spl_token_transfer(
+            TokenTransferParams{
+                source : ctx.accounts.edition_token.clone(),
+                destination : ctx.accounts.owner_token.clone(),
+                amount : edition_token.amount,
+                authority : ctx.accounts.pool_signer.clone(),
+                authority_signer_seeds : pool_signer_seeds,
+                token_program : ctx.accounts.token_program.clone(),
+            }
+        )?;
+        let pool = &mut ctx.accounts.pool;
+        pool.edition_mint = Pubkey::default();
         Ok(())
     }
 
-    pub fn sell_nft(
-        ctx : Context<SellNft>,
-        _price : u64,
+    pub fn set_max_supply(
+        ctx : Context<SetMaxSupply>,
+        _max_supply : u64,
         ) -> ProgramResult {
-        let pool = &ctx.accounts.pool;
-        let metadata_extended = &mut ctx.accounts.metadata_extended;
-        let sale_manager_key = ctx.accounts.sale_manager.key();
-        let sale_manager = &mut ctx.accounts.sale_manager;
-        sale_manager.sale_pot = ctx.accounts.sale_pot.key();
-        let sale_pot = &mut ctx.accounts.sale_pot;
-        let manager_pot : state::Account = state::Account::unpack_from_slice(&ctx.accounts.manager_pot.data.borrow())?;
-        let nft_seller_token : state::Account = state::Account::unpack_from_slice(&ctx.accounts.nft_seller_token.data.borrow())?;
-        let nft_manager_token : state::Account = state::Account::unpack_from_slice(&ctx.accounts.nft_manager_token.data.borrow())?;
-        let metadata : metaplex_token_metadata::state::Metadata =  metaplex_token_metadata::state::Metadata::from_account_info(&ctx.accounts.metadata)?;
-        if nft_seller_token.owner != *ctx.accounts.owner.key {
-            return Err(PoolError::InvalidTokenAccount.into());
-        }
-        if nft_seller_token.mint != *ctx.accounts.nft_mint.key {
-            return Err(PoolError::InvalidTokenAccount.into());
+        assert_pool_role(ctx.program_id, &ctx.accounts.pool, ctx.accounts.owner.key, &ctx.accounts.pool_role, ROLE_SALE_CONTROLLER)?;
+        let pool = &mut ctx.accounts.pool;
+        if _max_supply != 0 && _max_supply < pool.minted {
+            return Err(PoolError::InvalidMaxSupply.into());
         }
-        if nft_manager_token.mint != *ctx.accounts.nft_mint.key {
-            return Err(PoolError::InvalidTokenAccount.into());
+        // The reveal root only covers the supply it was committed with.
+        if is_hidden(pool) && (_max_supply == 0 || _max_supply > pool.max_supply) {
+            return Err(PoolError::InvalidMaxSupply.into());
         }
-        if manager_pot.mint != pool.sale_mint {
-            return Err(PoolError::InvalidMintAccount.into());
+        pool.max_supply = _max_supply;
+        Ok(())
+    }
+
+    pub fn set_mint_price(
+        ctx : Context<SetMintPrice>,
+        _mint_price : u64,
+        _mint_price_native : bool,
+        _phase_prices : Vec<u64>,
+        ) -> ProgramResult {
+        if _phase_prices.len() > MAX_MINT_PHASES {
+            return Err(PoolError::InvalidMintPhase.into());
         }
-        if manager_pot.owner != sale_manager_key {
-            return Err(PoolError::InvalidTokenAccount.into());
+        assert_pool_role(ctx.program_id, &ctx.accounts.pool, ctx.accounts.owner.key, &ctx.accounts.pool_role, ROLE_PRICING_MANAGER)?;
+        if ctx.accounts.pool.timelock_delay != 0 {
+            return Err(PoolError::TimelockActive.into());
         }
-        if metadata_extended.max_price < _price && metadata_extended.max_price!=0 {
-            return Err(PoolError::InvalidPrice.into());
+        let pool = &mut ctx.accounts.pool;
+        pool.mint_price = _mint_price;
+        pool.mint_price_native = _mint_price_native;
+        pool.phase_prices = _phase_prices;
+        Ok(())
+    }
+
+    pub fn withdraw_treasury(
+        ctx : Context<WithdrawTreasury>,
+        _amount : u64,
+        _native : bool,
+        ) -> ProgramResult {
+        let pool = &ctx.accounts.pool;
+        assert_pool_role(ctx.program_id, pool, ctx.accounts.owner.key, &ctx.accounts.pool_role, ROLE_TREASURER)?;
+        let pool_key = pool.key();
+        let treasury_seeds = &[
+            b"treasury".as_ref(),
+            pool_key.as_ref(),
+            &[pool.treasury_bump]
+        ];
+        if _native {
+            invoke_signed(
+                &system_instruction::transfer(
+                    ctx.accounts.treasury.key,
+                    ctx.accounts.destination.key,
+                    _amount,
+                ),
+                &[
+                    ctx.accounts.treasury.clone(),
+                    ctx.accounts.destination.clone(),
+                    ctx.accounts.system_program.to_account_info().clone(),
+                ],
+                &[treasury_seeds]
+            )?;
+        } else {
+            spl_token_transfer(
+                TokenTransferParams{
+                    source : ctx.accounts.treasury_token.clone(),
+                    destination : ctx.accounts.destination.clone(),
+                    amount : _amount,
+                    authority : ctx.accounts.treasury.clone(),
+                    authority_signer_seeds : treasury_seeds,
+                    token_program : ctx.accounts.token_program.clone(),
+                }
+            )?;
+            let pool = &mut ctx.accounts.pool;
+            pool.treasury_balance = pool.treasury_balance.saturating_sub(_amount);
         }
-        if metadata_extended.pool != ctx.accounts.pool.key(){
+        Ok(())
+    }
+
+    pub fn mint_nft<'info>(
+        ctx : Context<'_, '_, '_, 'info, MintNft<'info>>,
+        _bump : u8,
+        ) -> ProgramResult {
+        assert_not_paused(&ctx.accounts.pool, &ctx.accounts.config)?;
+        assert_mint_open(&ctx.accounts.pool, ctx.accounts.clock.unix_timestamp, false)?;
+        let client = &mut ctx.accounts.client;
+        let metadata_extended = &mut ctx.accounts.metadata_extended;
+        if client.pool != ctx.accounts.pool.key() {
             return Err(PoolError::InvalidPoolAccount.into());
         }
-        sale_manager.seller=*ctx.accounts.owner.key;
-        sale_manager.price=_price;
-        sale_manager.sale_state =1;
-        sale_manager.nft_pot = *ctx.accounts.nft_manager_token.key;
-
-        // sale_manager.is_primary = metadata.primary_sale_happened;
-        sale_pot.sale_manager = sale_manager_key;
-        sale_pot.is_used = false;
-        sale_pot.price = _price;
-        sale_pot.pool_pot=*ctx.accounts.manager_pot.key;
-        sale_pot.seller_fee_basis_points = metadata.data.seller_fee_basis_points;
-        sale_pot.is_primary = !metadata.primary_sale_happened;
-        if sale_pot.is_primary {
-            sale_pot.seller_verified = true;
-        } else {
-            sale_pot.seller_verified = false;
-        }
-        sale_pot.seller = *ctx.accounts.owner.key;
-        if let Some(creators) = metadata.data.creators{
-            for c in creators {
-                sale_pot.creators.push(Creator{
-                    address : c.address,
-                    verified : false,
-                    share : c.share,
-                })
-            }
+        if !client.whitelisted {
+            return Err(PoolError::NotWhitelisted.into());
         }
-        spl_token_transfer_without_seed(
-            TokenTransferParamsWithoutSeed{
-                source : ctx.accounts.nft_seller_token.clone(),
-                destination : ctx.accounts.nft_manager_token.clone(),
-                amount : 1,
-                authority : ctx.accounts.owner.clone(),
+        if client.amount == 0 {
+            return Err(PoolError::MintAmountIsZero.into());
+        }
+        let tier_price = assert_client_tier(&ctx.accounts.pool, client, ctx.accounts.clock.unix_timestamp, 1)?;
+
+        collect_mint_payment(
+            CollectMintPaymentParams{
+                program_id : ctx.program_id,
+                pool : &mut ctx.accounts.pool,
+                payer : ctx.accounts.owner.clone(),
+                payer_token : ctx.accounts.payer_token.clone(),
+                treasury : ctx.accounts.treasury.clone(),
+                treasury_token : ctx.accounts.treasury_token.clone(),
                 token_program : ctx.accounts.token_program.clone(),
+                system_program : ctx.accounts.system_program.to_account_info().clone(),
+                now : ctx.accounts.clock.unix_timestamp,
+                price : tier_price,
             }
         )?;
-        invoke(
-            &update_metadata_accounts(
-                *ctx.accounts.token_metadata_program.key,
-                *ctx.accounts.metadata.key,
-                *ctx.accounts.owner.key,
-                Some(sale_manager_key),
-                None,
-                None,
-            ),
-            &[
-                ctx.accounts.token_metadata_program.clone(),
-                ctx.accounts.metadata.clone(),
-                ctx.accounts.owner.clone(),
-            ]
+
+        let data = next_mint_metadata(ctx.program_id, &ctx.accounts.pool, &ctx.accounts.config_lines, &ctx.accounts.clock, ctx.accounts.mint.key)?;
+
+        mint_nft_with_metadata(
+            MintNftParams{
+                pool : ctx.accounts.pool.key(),
+                pool_signer : ctx.accounts.pool_signer.clone(),
+                signer_bump : ctx.accounts.pool.signer_bump,
+                hidden : is_hidden(&ctx.accounts.pool),
+                collection : ctx.accounts.pool.collection_mint,
+                collection_mint : ctx.accounts.collection_mint.clone(),
+                collection_metadata : ctx.accounts.collection_metadata.clone(),
+                collection_master_edition : ctx.accounts.collection_master_edition.clone(),
+                edition_mint : ctx.accounts.pool.edition_mint,
+                edition_accounts : ctx.remaining_accounts,
+                owner : ctx.accounts.owner.clone(),
+                mint : ctx.accounts.mint.clone(),
+                token_account : ctx.accounts.token_account.clone(),
+                metadata : ctx.accounts.metadata.clone(),
+                master_edition : ctx.accounts.master_edition.clone(),
+                token_metadata_program : ctx.accounts.token_metadata_program.clone(),
+                token_program : ctx.accounts.token_program.clone(),
+                system_program : ctx.accounts.system_program.to_account_info().clone(),
+                rent : ctx.accounts.rent.to_account_info().clone(),
+                data : data,
+            }
         )?;
+
+        client.amount = client.amount-1;
+        client.minted = client.minted+1;
+        metadata_extended.mint_index = ctx.accounts.pool.minted;
+        metadata_extended.hidden = is_hidden(&ctx.accounts.pool);
+        metadata_extended.legacy = false;
+        ctx.accounts.pool.minted = ctx.accounts.pool.minted + 1;
+        metadata_extended.version = METADATA_EXTENDED_VERSION;
+        metadata_extended.pool = ctx.accounts.pool.key();
+        metadata_extended.max_price = 0;
+        metadata_extended.bump = _bump;
+        metadata_extended.minter = *ctx.accounts.owner.key;
         Ok(())
     }
 
-    pub fn buy_nft(
-        ctx : Context<BuyNft>,
+    pub fn mint_nft_batch<'info>(
+        ctx : Context<'_, '_, '_, 'info, MintNftBatch<'info>>,
+        _bumps : Vec<u8>,
         ) -> ProgramResult {
-        let pool = &ctx.accounts.pool;
-        let pool_info = ctx.accounts.pool.to_account_info().clone();
-        let sale_manager_info1 = ctx.accounts.sale_manager.to_account_info().clone();
-        let sale_manager_info2 = ctx.accounts.sale_manager.to_account_info().clone();
-        let sale_manager_info3 = ctx.accounts.sale_manager.to_account_info().clone();
-        let sale_manager_key = ctx.accounts.sale_manager.key();
-        let sale_manager = &mut ctx.accounts.sale_manager;
-        let sale_pot = &mut ctx.accounts.sale_pot;
-        let nft_manager_token : state::Account = state::Account::unpack_from_slice(&ctx.accounts.nft_manager_token.data.borrow())?;
-        let nft_bidder_token : state::Account = state::Account::unpack_from_slice(&ctx.accounts.nft_bidder_token.data.borrow())?;
-        let manager_pot : state::Account = state::Account::unpack_from_slice(&ctx.accounts.manager_pot.data.borrow())?;
-        let bidder_token : state::Account = state::Account::unpack_from_slice(&ctx.accounts.bidder_token.data.borrow())?;
-        let metadata : metaplex_token_metadata::state::Metadata =  metaplex_token_metadata::state::Metadata::from_account_info(&ctx.accounts.metadata)?;
-
-        if nft_manager_token.mint != *ctx.accounts.nft_mint.key {
-            return Err(PoolError::InvalidTokenAccount.into());
+        assert_not_paused(&ctx.accounts.pool, &ctx.accounts.config)?;
+        if ctx.accounts.pool.edition_mint != Pubkey::default() {
+            return Err(PoolError::InvalidEdition.into());
         }
-        if nft_bidder_token.mint != *ctx.accounts.nft_mint.key {
-            return Err(PoolError::InvalidTokenAccount.into());
-        }
-        if manager_pot.mint != pool.sale_mint {
-            return Err(PoolError::InvalidTokenAccount.into());
-        }
-        if bidder_token.mint != pool.sale_mint {
-            return Err(PoolError::InvalidTokenAccount.into());
+        let count = _bumps.len();
+        if count == 0 || ctx.remaining_accounts.len() != count*MINT_BATCH_ACCOUNTS {
+            return Err(PoolError::InvalidMintBatch.into());
         }
-        if bidder_token.owner != *ctx.accounts.owner.key {
-            return Err(PoolError::InvalidTokenAccount.into());
+        let client = &ctx.accounts.client;
+        if client.pool != ctx.accounts.pool.key() {
+            return Err(PoolError::InvalidPoolAccount.into());
         }
-        if bidder_token.amount < sale_manager.price {
-            return Err(PoolError::NotEnoughTokenAmount.into());
+        if !client.whitelisted {
+            return Err(PoolError::NotWhitelisted.into());
         }
-        if sale_manager.sale_state != 1 {
-            return Err(PoolError::InvalidSaleState.into());
+        if client.amount < count as u64 {
+            return Err(PoolError::InvalidAmount.into());
         }
-        if sale_pot.pool_pot != *ctx.accounts.manager_pot.key {
-            return Err(PoolError::InvalidTokenAccount.into());
+        let tier_price = assert_client_tier(&ctx.accounts.pool, client, ctx.accounts.clock.unix_timestamp, count as u64)?;
+
+        let pool_key = ctx.accounts.pool.key();
+        // Each NFT takes mint, token account, metadata, master edition and
+        // metadata_extended from remaining_accounts, in that order.
+        for (accounts, bump) in ctx.remaining_accounts.chunks(MINT_BATCH_ACCOUNTS).zip(_bumps.iter()) {
+            assert_mint_open(&ctx.accounts.pool, ctx.accounts.clock.unix_timestamp, false)?;
+            let mint = &accounts[0];
+            let token_account = &accounts[1];
+            if *mint.owner != spl_token::id() || *token_account.owner != spl_token::id() {
+                return Err(PoolError::InvalidMintAccount.into());
+            }
+
+            collect_mint_payment(
+                CollectMintPaymentParams{
+                    program_id : ctx.program_id,
+                    pool : &mut ctx.accounts.pool,
+                    payer : ctx.accounts.owner.clone(),
+                    payer_token : ctx.accounts.payer_token.clone(),
+                    treasury : ctx.accounts.treasury.clone(),
+                    treasury_token : ctx.accounts.treasury_token.clone(),
+                    token_program : ctx.accounts.token_program.clone(),
+                    system_program : ctx.accounts.system_program.to_account_info().clone(),
+                    now : ctx.accounts.clock.unix_timestamp,
+                    price : tier_price,
+                }
+            )?;
+
+            create_metadata_extended(
+                MetadataExtendedParams{
+                    program_id : ctx.program_id,
+                    pool : pool_key,
+                    mint : mint.key,
+                    payer : ctx.accounts.owner.clone(),
+                    metadata_extended : accounts[4].clone(),
+                    bump : *bump,
+                    mint_index : ctx.accounts.pool.minted,
+                    hidden : is_hidden(&ctx.accounts.pool),
+                    system_program : ctx.accounts.system_program.to_account_info().clone(),
+                    rent : ctx.accounts.rent.to_account_info().clone(),
+                }
+            )?;
+
+            let data = next_mint_metadata(ctx.program_id, &ctx.accounts.pool, &ctx.accounts.config_lines, &ctx.accounts.clock, mint.key)?;
+            mint_nft_with_metadata(
+                MintNftParams{
+                    pool : pool_key,
+                    pool_signer : ctx.accounts.pool_signer.clone(),
+                    signer_bump : ctx.accounts.pool.signer_bump,
+                    hidden : is_hidden(&ctx.accounts.pool),
+                    collection : ctx.accounts.pool.collection_mint,
+                    collection_mint : ctx.accounts.collection_mint.clone(),
+                    collection_metadata : ctx.accounts.collection_metadata.clone(),
+                    collection_master_edition : ctx.accounts.collection_master_edition.clone(),
+                    edition_mint : Pubkey::default(),
+                    edition_accounts : &[],
+                    owner : ctx.accounts.owner.clone(),
+                    mint : mint.clone(),
+                    token_account : token_account.clone(),
+                    metadata : accounts[2].clone(),
+                    master_edition : accounts[3].clone(),
+                    token_metadata_program : ctx.accounts.token_metadata_program.clone(),
+                    token_program : ctx.accounts.token_program.clone(),
+                    system_program : ctx.accounts.system_program.to_account_info().clone(),
+                    rent : ctx.accounts.rent.to_account_info().clone(),
+                    data : data,
+                }
+            )?;
+            ctx.accounts.pool.minted = ctx.accounts.pool.minted + 1;
         }
-        if sale_manager.nft_mint != *ctx.accounts.nft_mint.key {
-            return Err(PoolError::InvalidMintAccount.into());
+
+        let client = &mut ctx.accounts.client;
+        client.amount = client.amount - count as u64;
+        client.minted = client.minted + count as u64;
+        Ok(())
+    }
+
+    pub fn mint_nft_with_proof<'info>(
+        ctx : Context<'_, '_, '_, 'info, MintNftWithProof<'info>>,
+        _bump : u8,
+        _record_bump : u8,
+        _allocation : u64,
+        _tier : u8,
+        _proof : Vec<[u8; 32]>,
+        ) -> ProgramResult {
+        assert_not_paused(&ctx.accounts.pool, &ctx.accounts.config)?;
+        let pool = &ctx.accounts.pool;
+        assert_mint_open(pool, ctx.accounts.clock.unix_timestamp, false)?;
+        if pool.merkle_root == [0; 32] {
+            return Err(PoolError::NotWhitelisted.into());
         }
-        if sale_manager.seller == *ctx.accounts.owner.key {
-            return Err(PoolError::InvalidBidder.into());
+        // The tier is part of the leaf, so a wallet cannot claim a cheaper one.
+        let leaf = hashv(&[ctx.accounts.owner.key.as_ref(), &_allocation.to_le_bytes(), &[_tier]]).to_bytes();
+        if !verify_merkle_proof(&_proof, &pool.merkle_root, leaf) {
+            return Err(PoolError::InvalidMerkleProof.into());
         }
-        spl_token_transfer_without_seed(
-            TokenTransferParamsWithoutSeed{
-                source : ctx.accounts.bidder_token.clone(),
-                destination : ctx.accounts.manager_pot.clone(),
-                amount : sale_manager.price,
-                authority : ctx.accounts.owner.clone(),
-                token_program : ctx.accounts.token_program.clone(),
+        let mut mint_record = load_mint_record(
+            MintRecordParams{
+                program_id : ctx.program_id,
+                seed : b"mint_record",
+                pool : pool.key(),
+                wallet : ctx.accounts.owner.clone(),
+                mint_record : ctx.accounts.mint_record.clone(),
+                bump : _record_bump,
+                system_program : ctx.accounts.system_program.to_account_info().clone(),
+                rent : ctx.accounts.rent.to_account_info().clone(),
             }
         )?;
+        if mint_record.minted >= _allocation {
+            return Err(PoolError::MintAmountIsZero.into());
+        }
 
-        let sale_manager_seeds = &[
-            sale_manager.pool.as_ref(),
-            sale_manager.nft_mint.as_ref(),
-            &[sale_manager.bump]
-        ];
-        invoke_signed(
-            &update_primary_sale_happened_via_token(
-                *ctx.accounts.token_metadata_program.key,
-                *ctx.accounts.metadata.key,
-                sale_manager_key,
-                *ctx.accounts.nft_manager_token.key,
-            ),
-            &[
-                ctx.accounts.token_metadata_program.clone(),
-                ctx.accounts.metadata.clone(),
-                sale_manager_info3,
-                ctx.accounts.nft_manager_token.clone(),
-            ],
-            &[sale_manager_seeds]
-        )?;
-        spl_token_transfer(
-            TokenTransferParams{
-                source : ctx.accounts.nft_manager_token.clone(),
-                destination : ctx.accounts.nft_bidder_token.clone(),
-                amount : 1,
-                authority : sale_manager_info1,
-                authority_signer_seeds : sale_manager_seeds,
+        let tier_price = assert_tier(pool, _tier, mint_record.minted, ctx.accounts.clock.unix_timestamp, 1)?;
+
+        collect_mint_payment(
+            CollectMintPaymentParams{
+                program_id : ctx.program_id,
+                pool : &mut ctx.accounts.pool,
+                payer : ctx.accounts.owner.clone(),
+                payer_token : ctx.accounts.payer_token.clone(),
+                treasury : ctx.accounts.treasury.clone(),
+                treasury_token : ctx.accounts.treasury_token.clone(),
                 token_program : ctx.accounts.token_program.clone(),
+                system_program : ctx.accounts.system_program.to_account_info().clone(),
+                now : ctx.accounts.clock.unix_timestamp,
+                price : tier_price,
             }
         )?;
 
-        invoke_signed(
-            &update_metadata_accounts(
-                *ctx.accounts.token_metadata_program.key,
-                *ctx.accounts.metadata.key,
-                sale_manager_key,
-                Some(*ctx.accounts.owner.key),
-                None,
-                None,
-            ),
-            &[
-                ctx.accounts.token_metadata_program.clone(),
-                ctx.accounts.metadata.clone(),
-                sale_manager_info2,
-            ],
-            &[sale_manager_seeds]
+        let data = next_mint_metadata(ctx.program_id, &ctx.accounts.pool, &ctx.accounts.config_lines, &ctx.accounts.clock, ctx.accounts.mint.key)?;
+
+        mint_nft_with_metadata(
+            MintNftParams{
+                pool : ctx.accounts.pool.key(),
+                pool_signer : ctx.accounts.pool_signer.clone(),
+                signer_bump : ctx.accounts.pool.signer_bump,
+                hidden : is_hidden(&ctx.accounts.pool),
+                collection : ctx.accounts.pool.collection_mint,
+                collection_mint : ctx.accounts.collection_mint.clone(),
+                collection_metadata : ctx.accounts.collection_metadata.clone(),
+                collection_master_edition : ctx.accounts.collection_master_edition.clone(),
+                edition_mint : ctx.accounts.pool.edition_mint,
+                edition_accounts : ctx.remaining_accounts,
+                owner : ctx.accounts.owner.clone(),
+                mint : ctx.accounts.mint.clone(),
+                token_account : ctx.accounts.token_account.clone(),
+                metadata : ctx.accounts.metadata.clone(),
+                master_edition : ctx.accounts.master_edition.clone(),
+                token_metadata_program : ctx.accounts.token_metadata_program.clone(),
+                token_program : ctx.accounts.token_program.clone(),
+                system_program : ctx.accounts.system_program.to_account_info().clone(),
+                rent : ctx.accounts.rent.to_account_info().clone(),
+                data : data,
+            }
         )?;
 
-        sale_manager.sale_state=2;
-        sale_pot.is_used=true;        
+        mint_record.minted = mint_record.minted + 1;
+        write_account(&mint_record, &ctx.accounts.mint_record)?;
+        let metadata_extended = &mut ctx.accounts.metadata_extended;
+        metadata_extended.mint_index = ctx.accounts.pool.minted;
+        metadata_extended.hidden = is_hidden(&ctx.accounts.pool);
+        metadata_extended.legacy = false;
+        ctx.accounts.pool.minted = ctx.accounts.pool.minted + 1;
+        metadata_extended.version = METADATA_EXTENDED_VERSION;
+        metadata_extended.pool = ctx.accounts.pool.key();
+        metadata_extended.max_price = 0;
+        metadata_extended.bump = _bump;
+        metadata_extended.minter = *ctx.accounts.owner.key;
         Ok(())
     }
 
-    pub fn redeem_nft(
-        ctx : Context<RedeemNft>
+    pub fn mint_nft_public<'info>(
+        ctx : Context<'_, '_, '_, 'info, MintNftPublic<'info>>,
+        _bump : u8,
+        _record_bump : u8,
         ) -> ProgramResult {
-        let sale_manager_info1 = ctx.accounts.sale_manager.to_account_info().clone();
-        let sale_manager_info2 = ctx.accounts.sale_manager.to_account_info().clone();
-        let sale_manager_key = ctx.accounts.sale_manager.key();
-        let sale_manager = &mut ctx.accounts.sale_manager;
-        
-        let nft_manager_token : state::Account = state::Account::unpack_from_slice(&ctx.accounts.nft_manager_token.data.borrow())?;
-        let nft_seller_token : state::Account = state::Account::unpack_from_slice(&ctx.accounts.nft_seller_token.data.borrow())?;
-        let metadata : metaplex_token_metadata::state::Metadata =  metaplex_token_metadata::state::Metadata::from_account_info(&ctx.accounts.metadata)?;
-        if sale_manager.sale_state != 1 {
-            return Err(PoolError::InvalidSaleState.into());
-        }
-        if sale_manager.nft_mint != *ctx.accounts.nft_mint.key {
-            return Err(PoolError::InvalidMintAccount.into());
-        }
-        if nft_manager_token.mint != *ctx.accounts.nft_mint.key {
-            return Err(PoolError::InvalidTokenAccount.into());
+        assert_not_paused(&ctx.accounts.pool, &ctx.accounts.config)?;
+        let pool = &ctx.accounts.pool;
+        if !pool.public_mint {
+            return Err(PoolError::PublicMintDisabled.into());
         }
-        if nft_seller_token.mint != *ctx.accounts.nft_mint.key {
-            return Err(PoolError::InvalidTokenAccount.into());
+        assert_mint_open(pool, ctx.accounts.clock.unix_timestamp, true)?;
+        let mut mint_record = load_mint_record(
+            MintRecordParams{
+                program_id : ctx.program_id,
+                seed : b"public_mint_record",
+                pool : pool.key(),
+                wallet : ctx.accounts.owner.clone(),
+                mint_record : ctx.accounts.mint_record.clone(),
+                bump : _record_bump,
+                system_program : ctx.accounts.system_program.to_account_info().clone(),
+                rent : ctx.accounts.rent.to_account_info().clone(),
+            }
+        )?;
+        if mint_record.minted >= pool.wallet_limit {
+            return Err(PoolError::WalletLimitReached.into());
         }
-        if sale_manager.seller != *ctx.accounts.owner.key {
-            return Err(PoolError::InvalidSeller.into())
-        }        
 
-        let sale_manager_seeds = &[
-            sale_manager.pool.as_ref(),
-            sale_manager.nft_mint.as_ref(),
-            &[sale_manager.bump]
-        ];
-        
-        spl_token_transfer(
-            TokenTransferParams{
-                source : ctx.accounts.nft_manager_token.clone(),
-                destination : ctx.accounts.nft_seller_token.clone(),
-                amount : 1,
-                authority : sale_manager_info1,
-                authority_signer_seeds : sale_manager_seeds,
+        collect_mint_payment(
+            CollectMintPaymentParams{
+                program_id : ctx.program_id,
+                pool : &mut ctx.accounts.pool,
+                payer : ctx.accounts.owner.clone(),
+                payer_token : ctx.accounts.payer_token.clone(),
+                treasury : ctx.accounts.treasury.clone(),
+                treasury_token : ctx.accounts.treasury_token.clone(),
                 token_program : ctx.accounts.token_program.clone(),
+                system_program : ctx.accounts.system_program.to_account_info().clone(),
+                now : ctx.accounts.clock.unix_timestamp,
+                price : None,
             }
         )?;
-        invoke_signed(
-            &update_metadata_accounts(
-                *ctx.accounts.token_metadata_program.key,
-                *ctx.accounts.metadata.key,
-                sale_manager_key,
-                Some(*ctx.accounts.owner.key),
-                None,
-                None,
-            ),
-            &[
-                ctx.accounts.token_metadata_program.clone(),
-                ctx.accounts.metadata.clone(),
-                sale_manager_info2,
-            ],
-            &[sale_manager_seeds]
+
+        let data = next_mint_metadata(ctx.program_id, &ctx.accounts.pool, &ctx.accounts.config_lines, &ctx.accounts.clock, ctx.accounts.mint.key)?;
+
+        mint_nft_with_metadata(
+            MintNftParams{
+                pool : ctx.accounts.pool.key(),
+                pool_signer : ctx.accounts.pool_signer.clone(),
+                signer_bump : ctx.accounts.pool.signer_bump,
+                hidden : is_hidden(&ctx.accounts.pool),
+                collection : ctx.accounts.pool.collection_mint,
+                collection_mint : ctx.accounts.collection_mint.clone(),
+                collection_metadata : ctx.accounts.collection_metadata.clone(),
+                collection_master_edition : ctx.accounts.collection_master_edition.clone(),
+                edition_mint : ctx.accounts.pool.edition_mint,
+                edition_accounts : ctx.remaining_accounts,
+                owner : ctx.accounts.owner.clone(),
+                mint : ctx.accounts.mint.clone(),
+                token_account : ctx.accounts.token_account.clone(),
+                metadata : ctx.accounts.metadata.clone(),
+                master_edition : ctx.accounts.master_edition.clone(),
+                token_metadata_program : ctx.accounts.token_metadata_program.clone(),
+                token_program : ctx.accounts.token_program.clone(),
+                system_program : ctx.accounts.system_program.to_account_info().clone(),
+                rent : ctx.accounts.rent.to_account_info().clone(),
+                data : data,
+            }
         )?;
-        sale_manager.sale_state=0;
+
+        mint_record.minted = mint_record.minted + 1;
+        write_account(&mint_record, &ctx.accounts.mint_record)?;
+        let metadata_extended = &mut ctx.accounts.metadata_extended;
+        metadata_extended.mint_index = ctx.accounts.pool.minted;
+        metadata_extended.hidden = is_hidden(&ctx.accounts.pool);
+        metadata_extended.legacy = false;
+        ctx.accounts.pool.minted = ctx.accounts.pool.minted + 1;
+        metadata_extended.version = METADATA_EXTENDED_VERSION;
+        metadata_extended.pool = ctx.accounts.pool.key();
+        metadata_extended.max_price = 0;
+        metadata_extended.bump = _bump;
+        metadata_extended.minter = *ctx.accounts.owner.key;
         Ok(())
     }
 
-    pub fn withdraw_fund(
-        ctx : Context<WithdrawFund>,
+    pub fn set_max_price(
+        ctx : Context<SetMaxPrice>,
+        _max_price : u64,
+        ) -> ProgramResult {
+        assert_pool_role(ctx.program_id, &ctx.accounts.pool, ctx.accounts.owner.key, &ctx.accounts.pool_role, ROLE_PRICING_MANAGER)?;
+        if ctx.accounts.pool.timelock_delay != 0 {
+            return Err(PoolError::TimelockActive.into());
+        }
+        let metadata_extended = &mut ctx.accounts.metadata_extended;
+        if metadata_extended.pool != ctx.accounts.pool.key() {
+            return Err(PoolError::InvalidPoolAccount.into());
+        }
+        metadata_extended.max_price=_max_price;
+        Ok(())
+    }
+
+    pub fn add_payment_mint(
+        ctx : Context<AddPaymentMint>,
+        ) -> ProgramResult {
+        assert_pool_role(ctx.program_id, &ctx.accounts.pool, ctx.accounts.owner.key, &ctx.accounts.pool_role, ROLE_PRICING_MANAGER)?;
+        let payment_mint : state::Mint = state::Mint::unpack_from_slice(&ctx.accounts.payment_mint.data.borrow())?;
+        let pool = &mut ctx.accounts.pool;
+        if pool.sale_mint == *ctx.accounts.payment_mint.key || pool.payment_mints.contains(ctx.accounts.payment_mint.key) {
+            return Err(PoolError::InvalidMintAccount.into());
+        }
+        if pool.payment_mints.len() >= MAX_PAYMENT_MINTS {
+            return Err(PoolError::TooManyPaymentMints.into());
+        }
+        pool.payment_mints.push(*ctx.accounts.payment_mint.key);
+        Ok(())
+    }
+
+    pub fn remove_payment_mint(
+        ctx : Context<RemovePaymentMint>,
+        _payment_mint : Pubkey,
+        ) -> ProgramResult {
+        assert_pool_role(ctx.program_id, &ctx.accounts.pool, ctx.accounts.owner.key, &ctx.accounts.pool_role, ROLE_PRICING_MANAGER)?;
+        let pool = &mut ctx.accounts.pool;
+        let index = pool.payment_mints.iter().position(|m| *m == _payment_mint)
+            .ok_or(PoolError::InvalidMintAccount)?;
+        pool.payment_mints.remove(index);
+        Ok(())
+    }
+
+    pub fn set_marketplace_fee(
+        ctx : Context<SetMarketplaceFee>,
+        _fee_basis_points : u16,
+        ) -> ProgramResult {
+        assert_pool_role(ctx.program_id, &ctx.accounts.pool, ctx.accounts.owner.key, &ctx.accounts.pool_role, ROLE_TREASURER)?;
+        if ctx.accounts.pool.timelock_delay != 0 {
+            return Err(PoolError::TimelockActive.into());
+        }
+        if _fee_basis_points > MAX_FEE_BASIS_POINTS {
+            return Err(PoolError::InvalidFee.into());
+        }
+        let pool = &mut ctx.accounts.pool;
+        pool.fee_basis_points = _fee_basis_points;
+        pool.fee_treasury = *ctx.accounts.fee_treasury.key;
+        Ok(())
+    }
+
+    pub fn set_timelock_delay(
+        ctx : Context<SetTimelockDelay>,
+        _delay : i64,
+        ) -> ProgramResult {
+        assert_pool_version(&ctx.accounts.pool)?;
+        let pool = &mut ctx.accounts.pool;
+        if _delay < pool.timelock_delay {
+            return Err(PoolError::TimelockActive.into());
+        }
+        if _delay > MAX_TIMELOCK_DELAY {
+            return Err(PoolError::InvalidTimelockDelay.into());
+        }
+        pool.timelock_delay = _delay;
+        Ok(())
+    }
+
+    // Once a pool has a delay, every setter that changes what minters pay or
+    // what holders get for a sale goes through this queue. set_collection and
+    // set_edition_mint stay direct: they only change what later mints print,
+    // move no funds, and need CPI accounts a queued change cannot carry.
+    pub fn queue_pool_change(
+        ctx : Context<QueuePoolChange>,
+        _change : PoolChange,
+        ) -> ProgramResult {
+        let pool = &ctx.accounts.pool;
+        let role = match &_change {
+            PoolChange::MaxPrice{..} => ROLE_PRICING_MANAGER,
+            PoolChange::SaleMint{..} => ROLE_PRICING_MANAGER,
+            PoolChange::MintPrice{phase_prices, ..} => {
+                if phase_prices.len() > MAX_MINT_PHASES {
+                    return Err(PoolError::InvalidMintPhase.into());
+                }
+                ROLE_PRICING_MANAGER
+            },
+            PoolChange::MintTiers{tiers} => {
+                validate_mint_tiers(tiers)?;
+                ROLE_SALE_CONTROLLER
+            },
+            PoolChange::MarketplaceFee{fee_basis_points, ..} => {
+                if *fee_basis_points > MAX_FEE_BASIS_POINTS {
+                    return Err(PoolError::InvalidFee.into());
+                }
+                ROLE_TREASURER
+            },
+            PoolChange::TimelockDelay{delay} => {
+                if *ctx.accounts.owner.key != pool.owner {
+                    return Err(PoolError::MissingPoolRole.into());
+                }
+                if *delay < 0 || *delay > MAX_TIMELOCK_DELAY {
+                    return Err(PoolError::InvalidTimelockDelay.into());
+                }
+                0
+            },
+        };
+        if role != 0 {
+            assert_pool_role(ctx.program_id, pool, ctx.accounts.owner.key, &ctx.accounts.pool_role, role)?;
+        }
+
+        let queued_change = &mut ctx.accounts.queued_change;
+        queued_change.pool = pool.key();
+        queued_change.payer = *ctx.accounts.owner.key;
+        queued_change.change = _change.clone();
+        queued_change.eta = ctx.accounts.clock.unix_timestamp.checked_add(pool.timelock_delay)
+            .ok_or(PoolError::InvalidTimelockDelay)?;
+        emit!(PoolChangeQueued{
+            pool : pool.key(),
+            queued_change : queued_change.key(),
+            change : _change,
+            eta : queued_change.eta,
+        });
+        Ok(())
+    }
+
+    pub fn execute_pool_change(
+        ctx : Context<ExecutePoolChange>,
+        ) -> ProgramResult {
+        assert_pool_version(&ctx.accounts.pool)?;
+        let queued_change = &ctx.accounts.queued_change;
+        if ctx.accounts.clock.unix_timestamp < queued_change.eta {
+            return Err(PoolError::TimelockNotExpired.into());
+        }
+        let pool_key = ctx.accounts.pool.key();
+        let pool = &mut ctx.accounts.pool;
+        match queued_change.change.clone() {
+            PoolChange::MaxPrice{metadata_extended, max_price} => {
+                let target = &ctx.accounts.target;
+                if *target.key != metadata_extended || target.owner != ctx.program_id {
+                    return Err(PoolError::InvalidChangeTarget.into());
+                }
+                let mut data : MetadataExtended = MetadataExtended::try_deserialize(&mut &target.data.borrow()[..])?;
+                if data.pool != pool_key {
+                    return Err(PoolError::InvalidPoolAccount.into());
+                }
+                data.max_price = max_price;
+                let mut target_data = target.try_borrow_mut_data()?;
+                let dst : &mut [u8] = &mut target_data;
+                let mut cursor = std::io::Cursor::new(dst);
+                data.try_serialize(&mut cursor)?;
+            },
+            PoolChange::MarketplaceFee{fee_basis_points, fee_treasury} => {
+                pool.fee_basis_points = fee_basis_points;
+                pool.fee_treasury = fee_treasury;
+            },
+            PoolChange::SaleMint{sale_mint} => {
+                let target = &ctx.accounts.target;
+                if *target.key != sale_mint || *target.owner != spl_token::id() {
+                    return Err(PoolError::InvalidChangeTarget.into());
+                }
+                let mint : state::Mint = state::Mint::unpack_from_slice(&target.data.borrow())?;
+                if !mint.is_initialized {
+                    return Err(PoolError::InvalidMintAccount.into());
+                }
+                pool.sale_mint = sale_mint;
+            },
+            PoolChange::TimelockDelay{delay} => {
+                pool.timelock_delay = delay;
+            },
+            PoolChange::MintPrice{mint_price, mint_price_native, phase_prices} => {
+                pool.mint_price = mint_price;
+                pool.mint_price_native = mint_price_native;
+                pool.phase_prices = phase_prices;
+            },
+            PoolChange::MintTiers{tiers} => {
+                pool.tiers = tiers;
+            },
+        }
+        emit!(PoolChangeExecuted{
+            pool : pool_key,
+            queued_change : queued_change.key(),
+        });
+        Ok(())
+    }
+
+    pub fn cancel_pool_change(
+        ctx : Context<CancelPoolChange>,
+        ) -> ProgramResult {
+        emit!(PoolChangeCancelled{
+            pool : ctx.accounts.pool.key(),
+            queued_change : ctx.accounts.queued_change.key(),
+        });
+        Ok(())
+    }
+
+    pub fn init_sale_manager(
+        ctx : Context<InitSaleManager>,
+        _bump : u8
+        ) -> ProgramResult {
+        let sale_manager = &mut ctx.accounts.sale_manager;
+        sale_manager.version = SALE_MANAGER_VERSION;
+        sale_manager.bump = _bump;
+        sale_manager.pool = ctx.accounts.pool.key();
+        sale_manager.nft_mint = *ctx.accounts.nft_mint.key;
+        sale_manager.sale_state = 0;
+        Ok(())
+    }
+
+    pub fn sell_nft(
+        ctx : Context<SellNft>,
+        _price : u64,
+        ) -> ProgramResult {
+        let pool = &ctx.accounts.pool;
+        assert_not_paused(pool, &ctx.accounts.config)?;
+        let sale_manager_key = ctx.accounts.sale_manager.key();
+        let manager_pot : state::Account = state::Account::unpack_from_slice(&ctx.accounts.manager_pot.data.borrow())?;
+        assert_payment_mint(pool, &manager_pot.mint)?;
+        if manager_pot.owner != sale_manager_key {
+            return Err(PoolError::InvalidTokenAccount.into());
+        }
+        list_nft(
+            ListNftParams{
+                program_id : ctx.program_id,
+                pool : pool,
+                pool_key : pool.key(),
+                metadata_extended : &ctx.accounts.metadata_extended,
+                sale_manager : &mut ctx.accounts.sale_manager,
+                sale_manager_key : sale_manager_key,
+                sale_pot_key : ctx.accounts.sale_pot.key(),
+                sale_pot : &mut ctx.accounts.sale_pot,
+                seller : ctx.accounts.owner.clone(),
+                nft_mint : ctx.accounts.nft_mint.key,
+                nft_seller_token : ctx.accounts.nft_seller_token.clone(),
+                nft_manager_token : ctx.accounts.nft_manager_token.clone(),
+                metadata : ctx.accounts.metadata.clone(),
+                token_metadata_program : ctx.accounts.token_metadata_program.clone(),
+                token_program : ctx.accounts.token_program.clone(),
+                price : _price,
+                payment : Some((*ctx.accounts.manager_pot.key, manager_pot.mint)),
+            }
+        )?;
+        let pool = &mut ctx.accounts.pool;
+        pool.active_listings = pool.active_listings + 1;
+        Ok(())
+    }
+
+    pub fn buy_nft(
+        ctx : Context<BuyNft>,
         ) -> ProgramResult {
+        let pool = &ctx.accounts.pool;
+        assert_not_paused(pool, &ctx.accounts.config)?;
         let sale_manager_info = ctx.accounts.sale_manager.to_account_info().clone();
-        let sale_manager = &ctx.accounts.sale_manager;
-        let sale_pot = &mut ctx.accounts.sale_pot;
+        let sale_pot = &ctx.accounts.sale_pot;
+        let fee = assert_purchase(
+            &ctx.accounts.sale_manager,
+            sale_pot,
+            &sale_pot.key(),
+            ctx.accounts.nft_mint.key,
+            &ctx.accounts.nft_manager_token,
+            &ctx.accounts.nft_bidder_token,
+            ctx.accounts.owner.key,
+            false,
+        )?;
+        let price = ctx.accounts.sale_manager.price;
+        let manager_pot : state::Account = state::Account::unpack_from_slice(&ctx.accounts.manager_pot.data.borrow())?;
+        let bidder_token : state::Account = state::Account::unpack_from_slice(&ctx.accounts.bidder_token.data.borrow())?;
+        let payment_mint = sale_pot_payment_mint(pool, sale_pot);
+        if manager_pot.mint != payment_mint {
+            return Err(PoolError::InvalidTokenAccount.into());
+        }
+        if bidder_token.mint != payment_mint {
+            return Err(PoolError::InvalidTokenAccount.into());
+        }
+        if bidder_token.owner != *ctx.accounts.owner.key {
+            return Err(PoolError::InvalidTokenAccount.into());
+        }
+        if bidder_token.amount < price {
+            return Err(PoolError::NotEnoughTokenAmount.into());
+        }
+        if sale_pot.pool_pot != *ctx.accounts.manager_pot.key {
+            return Err(PoolError::InvalidTokenAccount.into());
+        }
+        if fee != 0 {
+            let treasury_token : state::Account = state::Account::unpack_from_slice(&ctx.accounts.treasury_token.data.borrow())?;
+            if treasury_token.owner != pool.fee_treasury || treasury_token.mint != payment_mint {
+                return Err(PoolError::InvalidTokenAccount.into());
+            }
+            spl_token_transfer_without_seed(
+                TokenTransferParamsWithoutSeed{
+                    source : ctx.accounts.bidder_token.clone(),
+                    destination : ctx.accounts.treasury_token.clone(),
+                    amount : fee,
+                    authority : ctx.accounts.owner.clone(),
+                    token_program : ctx.accounts.token_program.clone(),
+                }
+            )?;
+        }
+        spl_token_transfer_without_seed(
+            TokenTransferParamsWithoutSeed{
+                source : ctx.accounts.bidder_token.clone(),
+                destination : ctx.accounts.manager_pot.clone(),
+                amount : price - fee,
+                authority : ctx.accounts.owner.clone(),
+                token_program : ctx.accounts.token_program.clone(),
+            }
+        )?;
+
+        deliver_sold_nft(
+            DeliverNftParams{
+                sale_manager : &mut ctx.accounts.sale_manager,
+                sale_manager_info : sale_manager_info,
+                sale_pot : &mut ctx.accounts.sale_pot,
+                buyer : ctx.accounts.owner.clone(),
+                nft_manager_token : ctx.accounts.nft_manager_token.clone(),
+                nft_bidder_token : ctx.accounts.nft_bidder_token.clone(),
+                metadata : ctx.accounts.metadata.clone(),
+                token_metadata_program : ctx.accounts.token_metadata_program.clone(),
+                token_program : ctx.accounts.token_program.clone(),
+                escrow : price - fee,
+            }
+        )?;
+        let pool = &mut ctx.accounts.pool;
+        pool.active_listings = pool.active_listings.saturating_sub(1);
+        Ok(())
+    }
+
+    pub fn sell_nft_native(
+        ctx : Context<SellNftNative>,
+        _price : u64,
+        ) -> ProgramResult {
+        let pool = &ctx.accounts.pool;
+        assert_not_paused(pool, &ctx.accounts.config)?;
+        list_nft(
+            ListNftParams{
+                program_id : ctx.program_id,
+                pool : pool,
+                pool_key : pool.key(),
+                metadata_extended : &ctx.accounts.metadata_extended,
+                sale_manager_key : ctx.accounts.sale_manager.key(),
+                sale_manager : &mut ctx.accounts.sale_manager,
+                sale_pot_key : ctx.accounts.sale_pot.key(),
+                sale_pot : &mut ctx.accounts.sale_pot,
+                seller : ctx.accounts.owner.clone(),
+                nft_mint : ctx.accounts.nft_mint.key,
+                nft_seller_token : ctx.accounts.nft_seller_token.clone(),
+                nft_manager_token : ctx.accounts.nft_manager_token.clone(),
+                metadata : ctx.accounts.metadata.clone(),
+                token_metadata_program : ctx.accounts.token_metadata_program.clone(),
+                token_program : ctx.accounts.token_program.clone(),
+                price : _price,
+                payment : None,
+            }
+        )?;
+        let pool = &mut ctx.accounts.pool;
+        pool.active_listings = pool.active_listings + 1;
+        Ok(())
+    }
+
+    pub fn buy_nft_native(
+        ctx : Context<BuyNftNative>,
+        ) -> ProgramResult {
+        let pool = &ctx.accounts.pool;
+        assert_not_paused(pool, &ctx.accounts.config)?;
+        let sale_manager_info = ctx.accounts.sale_manager.to_account_info().clone();
+        let sale_manager_key = ctx.accounts.sale_manager.key();
+        let fee = assert_purchase(
+            &ctx.accounts.sale_manager,
+            &ctx.accounts.sale_pot,
+            &ctx.accounts.sale_pot.key(),
+            ctx.accounts.nft_mint.key,
+            &ctx.accounts.nft_manager_token,
+            &ctx.accounts.nft_bidder_token,
+            ctx.accounts.owner.key,
+            true,
+        )?;
+        let price = ctx.accounts.sale_manager.price;
+        if ctx.accounts.owner.lamports() < price {
+            return Err(PoolError::NotEnoughTokenAmount.into());
+        }
+        if fee != 0 {
+            if *ctx.accounts.fee_treasury.key != pool.fee_treasury {
+                return Err(PoolError::InvalidFeeTreasury.into());
+            }
+            invoke(
+                &system_instruction::transfer(
+                    ctx.accounts.owner.key,
+                    ctx.accounts.fee_treasury.key,
+                    fee,
+                ),
+                &[
+                    ctx.accounts.owner.clone(),
+                    ctx.accounts.fee_treasury.clone(),
+                    ctx.accounts.system_program.to_account_info().clone(),
+                ]
+            )?;
+        }
+        invoke(
+            &system_instruction::transfer(
+                ctx.accounts.owner.key,
+                &sale_manager_key,
+                price - fee,
+            ),
+            &[
+                ctx.accounts.owner.clone(),
+                sale_manager_info.clone(),
+                ctx.accounts.system_program.to_account_info().clone(),
+            ]
+        )?;
+
+        deliver_sold_nft(
+            DeliverNftParams{
+                sale_manager : &mut ctx.accounts.sale_manager,
+                sale_manager_info : sale_manager_info,
+                sale_pot : &mut ctx.accounts.sale_pot,
+                buyer : ctx.accounts.owner.clone(),
+                nft_manager_token : ctx.accounts.nft_manager_token.clone(),
+                nft_bidder_token : ctx.accounts.nft_bidder_token.clone(),
+                metadata : ctx.accounts.metadata.clone(),
+                token_metadata_program : ctx.accounts.token_metadata_program.clone(),
+                token_program : ctx.accounts.token_program.clone(),
+                escrow : price - fee,
+            }
+        )?;
+        let pool = &mut ctx.accounts.pool;
+        pool.active_listings = pool.active_listings.saturating_sub(1);
+        Ok(())
+    }
+
+    pub fn redeem_nft(
+        ctx : Context<RedeemNft>
+        ) -> ProgramResult {
+        assert_pool_version(&ctx.accounts.pool)?;
+        let sale_manager_info1 = ctx.accounts.sale_manager.clone();
+        let sale_manager_info2 = ctx.accounts.sale_manager.clone();
+        let sale_manager_key = *ctx.accounts.sale_manager.key;
+        let (mut sale_manager, legacy) = load_sale_manager(ctx.program_id, &ctx.accounts.sale_manager)?;
+        assert_sale_manager(ctx.program_id, &ctx.accounts.pool, &sale_manager, legacy, &ctx.accounts.sale_manager)?;
+
+        let nft_manager_token : state::Account = state::Account::unpack_from_slice(&ctx.accounts.nft_manager_token.data.borrow())?;
+        let nft_seller_token : state::Account = state::Account::unpack_from_slice(&ctx.accounts.nft_seller_token.data.borrow())?;
+        let metadata : metaplex_token_metadata::state::Metadata =  metaplex_token_metadata::state::Metadata::from_account_info(&ctx.accounts.metadata)?;
+        if sale_manager.sale_state != 1 {
+            return Err(PoolError::InvalidSaleState.into());
+        }
+        if sale_manager.nft_mint != *ctx.accounts.nft_mint.key {
+            return Err(PoolError::InvalidMintAccount.into());
+        }
+        if nft_manager_token.mint != *ctx.accounts.nft_mint.key {
+            return Err(PoolError::InvalidTokenAccount.into());
+        }
+        if nft_seller_token.mint != *ctx.accounts.nft_mint.key {
+            return Err(PoolError::InvalidTokenAccount.into());
+        }
+        if sale_manager.seller != *ctx.accounts.owner.key {
+            return Err(PoolError::InvalidSeller.into())
+        }        
+
         let sale_manager_seeds = &[
             sale_manager.pool.as_ref(),
             sale_manager.nft_mint.as_ref(),
             &[sale_manager.bump]
         ];
-        let pool_pot : state::Account = state::Account::unpack_from_slice(&ctx.accounts.pool_pot.data.borrow())?;
-        let mut amount : u64 = 0;
-        if sale_pot.is_primary {
-            // sale_pot.seller_verified = true;
-            let mut share : u8 = 0;
-            for i in 0..sale_pot.creators.len(){
-                if sale_pot.creators[i].verified==false && sale_pot.creators[i].address==*ctx.accounts.owner.key {
-                    sale_pot.creators[i].verified=true;
-                    share = sale_pot.creators[i].share
-                }
-            }
-            amount =((sale_pot.price as f64) * (share as f64) / (100.0 as f64)) as u64;
-        } else {
-            if sale_pot.seller_verified==false && sale_pot.seller == *ctx.accounts.owner.key {
-                amount = ((sale_pot.price as f64) * ((10000-sale_pot.seller_fee_basis_points) as f64) / (10000 as f64)) as u64;
-                sale_pot.seller_verified = true;
-            }
-            let mut share : u8 = 0;
-            for i in 0..sale_pot.creators.len(){
-                if sale_pot.creators[i].verified==false && sale_pot.creators[i].address==*ctx.accounts.owner.key {
-                    sale_pot.creators[i].verified=true;
-                    share = sale_pot.creators[i].share
-                }
+        
+        spl_token_transfer(
+            TokenTransferParams{
+                source : ctx.accounts.nft_manager_token.clone(),
+                destination : ctx.accounts.nft_seller_token.clone(),
+                amount : 1,
+                authority : sale_manager_info1,
+                authority_signer_seeds : sale_manager_seeds,
+                token_program : ctx.accounts.token_program.clone(),
             }
-            amount = amount + ((sale_pot.price as f64) * (sale_pot.seller_fee_basis_points as f64) / (10000 as f64) * (share as f64) / (100.0 as f64)) as u64
+        )?;
+        invoke_signed(
+            &update_metadata_accounts(
+                *ctx.accounts.token_metadata_program.key,
+                *ctx.accounts.metadata.key,
+                sale_manager_key,
+                Some(*ctx.accounts.owner.key),
+                None,
+                None,
+            ),
+            &[
+                ctx.accounts.token_metadata_program.clone(),
+                ctx.accounts.metadata.clone(),
+                sale_manager_info2,
+            ],
+            &[sale_manager_seeds]
+        )?;
+        sale_manager.sale_state=0;
+        store_sale_manager(&sale_manager, legacy, &ctx.accounts.sale_manager)?;
+        // Listings made before the pool was migrated were never counted.
+        if !legacy {
+            let pool = &mut ctx.accounts.pool;
+            pool.active_listings = pool.active_listings.saturating_sub(1);
+        }
+        Ok(())
+    }
+
+    pub fn withdraw_fund(
+        ctx : Context<WithdrawFund>,
+        ) -> ProgramResult {
+        assert_not_paused(&ctx.accounts.pool, &ctx.accounts.config)?;
+        let sale_manager_info = ctx.accounts.sale_manager.clone();
+        let (sale_manager, legacy) = load_sale_manager(ctx.program_id, &ctx.accounts.sale_manager)?;
+        assert_sale_manager(ctx.program_id, &ctx.accounts.pool, &sale_manager, legacy, &ctx.accounts.sale_manager)?;
+        let (mut sale_pot, legacy_pot) = load_sale_pot(ctx.program_id, &ctx.accounts.sale_pot)?;
+        let sale_manager_seeds = &[
+            sale_manager.pool.as_ref(),
+            sale_manager.nft_mint.as_ref(),
+            &[sale_manager.bump]
+        ];
+        if sale_pot.sale_manager != *ctx.accounts.sale_manager.key || sale_pot.is_native || !sale_pot.is_used {
+            return Err(PoolError::InvalidSalePot.into());
+        }
+        if sale_pot.pool_pot != *ctx.accounts.pool_pot.key {
+            return Err(PoolError::InvalidTokenAccount.into());
+        }
+        let pool_pot : state::Account = state::Account::unpack_from_slice(&ctx.accounts.pool_pot.data.borrow())?;
+        let withdraw_pot : state::Account = state::Account::unpack_from_slice(&ctx.accounts.withdraw_pot.data.borrow())?;
+        let payment_mint = sale_pot_payment_mint(&ctx.accounts.pool, &sale_pot);
+        if pool_pot.mint != payment_mint || withdraw_pot.mint != payment_mint {
+            return Err(PoolError::InvalidMintAccount.into());
         }
+        let mut amount = claim_withdraw_amount(&mut sale_pot, ctx.accounts.owner.key);
         if amount == 0 {
             return Err(PoolError::InvalidAmount.into());
         }
 
-        if amount > pool_pot.amount {
-            amount = pool_pot.amount;
-        }
-        
-        spl_token_transfer(
-            TokenTransferParams{
-                source : ctx.accounts.pool_pot.clone(),
-                destination : ctx.accounts.withdraw_pot.clone(),
-                amount : amount,
-                authority : sale_manager_info,
-                authority_signer_seeds : sale_manager_seeds,
-                token_program : ctx.accounts.token_program.clone(),
-            }
-        )?;
+        if amount > pool_pot.amount {
+            amount = pool_pot.amount;
+        }
+        amount = take_sale_pot_escrow(&mut sale_pot, amount);
+        store_sale_pot(&sale_pot, legacy_pot, &ctx.accounts.sale_pot)?;
+
+        spl_token_transfer(
+            TokenTransferParams{
+                source : ctx.accounts.pool_pot.clone(),
+                destination : ctx.accounts.withdraw_pot.clone(),
+                amount : amount,
+                authority : sale_manager_info,
+                authority_signer_seeds : sale_manager_seeds,
+                token_program : ctx.accounts.token_program.clone(),
+            }
+        )?;
+
+        Ok(())
+    }
+
+    pub fn withdraw_fund_native(
+        ctx : Context<WithdrawFundNative>,
+        ) -> ProgramResult {
+        assert_not_paused(&ctx.accounts.pool, &ctx.accounts.config)?;
+        let sale_manager_info = ctx.accounts.sale_manager.to_account_info().clone();
+        let sale_manager = &ctx.accounts.sale_manager;
+        if sale_manager.pool != ctx.accounts.pool.key() {
+            return Err(PoolError::InvalidPoolAccount.into());
+        }
+        let sale_pot = &mut ctx.accounts.sale_pot;
+        // Only a sold pot has proceeds; the sale manager's lamports are shared by
+        // every listing of this NFT, so each pot pays out of its own escrow.
+        if sale_pot.sale_manager != sale_manager.key() || !sale_pot.is_native || !sale_pot.is_used {
+            return Err(PoolError::InvalidSalePot.into());
+        }
+        let mut amount = claim_withdraw_amount(sale_pot, ctx.accounts.owner.key);
+        if amount == 0 {
+            return Err(PoolError::InvalidAmount.into());
+        }
+
+        let rent_exempt = ctx.accounts.rent.minimum_balance(sale_manager_info.data_len());
+        let balance = sale_manager_info.lamports().saturating_sub(rent_exempt);
+        if amount > balance {
+            amount = balance;
+        }
+        amount = take_sale_pot_escrow(sale_pot, amount);
+
+        **sale_manager_info.try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.owner.try_borrow_mut_lamports()? += amount;
+
+        Ok(())
+    }
+
+    pub fn close_pool(
+        ctx : Context<ClosePool>,
+        ) -> ProgramResult {
+        assert_pool_version(&ctx.accounts.pool)?;
+        let pool = &ctx.accounts.pool;
+        if pool.presale_live {
+            return Err(PoolError::PresaleLive.into());
+        }
+        if pool.active_listings != 0 {
+            return Err(PoolError::ActiveListings.into());
+        }
+        if pool.role_count != 0 || pool.config_lines_count != 0 {
+            return Err(PoolError::OpenPoolAccounts.into());
+        }
+        if ctx.accounts.treasury.lamports() != 0 || pool.treasury_balance != 0 {
+            return Err(PoolError::TreasuryNotEmpty.into());
+        }
+        // The multisig can only ever act on this pool, so it goes with it.
+        let (pool_authority, _) = Pubkey::find_program_address(&[b"pool_authority".as_ref(), pool.key().as_ref()], ctx.program_id);
+        if *ctx.accounts.pool_authority.key != pool_authority {
+            return Err(PoolError::InvalidPoolAuthority.into());
+        }
+        if ctx.accounts.pool_authority.owner == ctx.program_id {
+            close_account(&ctx.accounts.pool_authority, &ctx.accounts.recipient)?;
+        }
+        if pool.pool_registry != Pubkey::default() {
+            if *ctx.accounts.pool_registry.key != pool.pool_registry {
+                return Err(PoolError::InvalidPoolRegistry.into());
+            }
+            let mut pool_registry : PoolRegistry = PoolRegistry::try_deserialize(&mut &ctx.accounts.pool_registry.data.borrow()[..])?;
+            pool_registry.active_pools = pool_registry.active_pools - 1;
+            write_account(&pool_registry, &ctx.accounts.pool_registry)?;
+        }
+        Ok(())
+    }
+
+    // A registry only seeds its owner's next pool address, so closing it while
+    // pools remain just makes the next init_registered_pool collide with them.
+    pub fn close_pool_registry(
+        ctx : Context<ClosePoolRegistry>,
+        ) -> ProgramResult {
+        if ctx.accounts.pool_registry.active_pools != 0 {
+            return Err(PoolError::ActivePools.into());
+        }
+        Ok(())
+    }
+
+    pub fn close_client(
+        ctx : Context<CloseClient>,
+        ) -> ProgramResult {
+        let pool = &ctx.accounts.pool;
+        let client = &ctx.accounts.client;
+        if *ctx.accounts.authority.key != pool.owner && *ctx.accounts.authority.key != client.owner {
+            return Err(PoolError::InvalidCloseAuthority.into());
+        }
+        Ok(())
+    }
+
+    pub fn close_metadata_extended(
+        ctx : Context<CloseMetadataExtended>,
+        ) -> ProgramResult {
+        let pool = &ctx.accounts.pool;
+        let metadata_extended = &ctx.accounts.metadata_extended;
+        if metadata_extended.pool != *pool.key {
+            return Err(PoolError::InvalidPoolAccount.into());
+        }
+        // While the pool is open, a live NFT still needs this account to list,
+        // reveal and prove it was minted here; once the pool is closed the rent
+        // just goes back to the minter.
+        if pool.owner == ctx.program_id {
+            let pool_data : Pool = Pool::try_deserialize(&mut &pool.data.borrow()[..])?;
+            if *ctx.accounts.authority.key != pool_data.owner {
+                return Err(PoolError::InvalidCloseAuthority.into());
+            }
+            let mint : state::Mint = state::Mint::unpack_from_slice(&ctx.accounts.mint.data.borrow())?;
+            if mint.supply != 0 {
+                return Err(PoolError::MetadataInUse.into());
+            }
+        }
+        let (sale_manager, _) = Pubkey::find_program_address(&[pool.key.as_ref(), ctx.accounts.mint.key.as_ref()], ctx.program_id);
+        if *ctx.accounts.sale_manager.key != sale_manager {
+            return Err(PoolError::InvalidSaleManager.into());
+        }
+        if ctx.accounts.sale_manager.owner == ctx.program_id {
+            let sale_manager : SaleManager = SaleManager::try_deserialize(&mut &ctx.accounts.sale_manager.data.borrow()[..])?;
+            if sale_manager.sale_state != 0 {
+                return Err(PoolError::MetadataInUse.into());
+            }
+        }
+        if *ctx.accounts.minter.key != metadata_extended.minter {
+            return Err(PoolError::InvalidRecipient.into());
+        }
+        Ok(())
+    }
+
+    pub fn close_sale_manager(
+        ctx : Context<CloseSaleManager>,
+        ) -> ProgramResult {
+        let sale_manager = &ctx.accounts.sale_manager;
+        // A sold NFT is gone from escrow; its manager can go once every share of
+        // the last sale pot has been withdrawn.
+        if sale_manager.sale_state == 2 {
+            if *ctx.accounts.sale_pot.key != sale_manager.sale_pot {
+                return Err(PoolError::InvalidSalePot.into());
+            }
+            let sale_pot : SalePot = SalePot::try_deserialize(&mut &ctx.accounts.sale_pot.data.borrow()[..])?;
+            if sale_pot.escrow != 0 {
+                return Err(PoolError::EscrowNotEmpty.into());
+            }
+        } else if sale_manager.sale_state != 0 {
+            return Err(PoolError::InvalidSaleState.into());
+        }
+        // Native sale proceeds are escrowed in the sale manager's lamports.
+        let sale_manager_info = sale_manager.to_account_info();
+        if sale_manager_info.lamports() > ctx.accounts.rent.minimum_balance(sale_manager_info.data_len()) {
+            return Err(PoolError::EscrowNotEmpty.into());
+        }
+        Ok(())
+    }
+
+    pub fn migrate_pool(
+        ctx : Context<MigratePool>,
+        _bump : u8,
+        ) -> ProgramResult {
+        let legacy : LegacyPool = load_legacy_account::<Pool, LegacyPool>(ctx.program_id, &ctx.accounts.legacy_pool, LEGACY_POOL_SIZE)?;
+        let pool = &mut ctx.accounts.pool;
+        pool.version = POOL_VERSION;
+        pool.owner = legacy.owner;
+        pool.presale_live = legacy.presale_live;
+        pool.sale_mint = legacy.sale_mint;
+        pool.pending_owner = Pubkey::default();
+        pool.paused = false;
+        pool.pool_registry = Pubkey::default();
+        pool.registry_index = 0;
+        pool.bump = _bump;
+        pool.active_listings = 0;
+        pool.payment_mints = vec![];
+        pool.fee_basis_points = 0;
+        pool.fee_treasury = Pubkey::default();
+        pool.timelock_delay = 0;
+        pool.merkle_root = [0; 32];
+        pool.phases = vec![];
+        pool.public_mint = false;
+        pool.wallet_limit = 0;
+        pool.mint_price = 0;
+        pool.mint_price_native = false;
+        pool.treasury_bump = treasury_bump(ctx.program_id, &pool.key());
+        pool.phase_prices = Vec::new();
+        pool.max_supply = 0;
+        pool.minted = 0;
+        pool.signer_bump = pool_signer_bump(ctx.program_id, &pool.key());
+        pool.collection_mint = Pubkey::default();
+        pool.edition_mint = Pubkey::default();
+        pool.hidden_name = String::new();
+        pool.hidden_uri = String::new();
+        pool.reveal_root = [0; 32];
+        pool.config_lines = Pubkey::default();
+        pool.legacy_pool = *ctx.accounts.legacy_pool.key;
+        pool.role_count = 0;
+        pool.config_lines_count = 0;
+        pool.treasury_balance = 0;
+        pool.tiers = vec![];
+        close_account(&ctx.accounts.legacy_pool, &ctx.accounts.payer)
+    }
+
+    pub fn migrate_client(
+        ctx : Context<MigrateClient>,
+        _bump : u8,
+        ) -> ProgramResult {
+        let legacy : LegacyClient = load_legacy_account::<Client, LegacyClient>(ctx.program_id, &ctx.accounts.legacy_client, LEGACY_CLIENT_SIZE)?;
+        let pool = &ctx.accounts.pool;
+        if pool.legacy_pool == Pubkey::default() || legacy.pool != pool.legacy_pool || legacy.owner != *ctx.accounts.bidder.key {
+            return Err(PoolError::InvalidClientAccount.into());
+        }
+        let client = &mut ctx.accounts.client;
+        client.version = CLIENT_VERSION;
+        client.owner = legacy.owner;
+        client.pool = pool.key();
+        client.amount = legacy.amount;
+        client.whitelisted = legacy.whitelisted;
+        client.bump = _bump;
+        client.tier = 0;
+        client.minted = 0;
+        close_account(&ctx.accounts.legacy_client, &ctx.accounts.payer)
+    }
+
+    // The legacy account does not record its mint, so its address is checked
+    // against the mint instead.
+    pub fn migrate_metadata_extended(
+        ctx : Context<MigrateMetadataExtended>,
+        _bump : u8,
+        ) -> ProgramResult {
+        let legacy : LegacyMetadataExtended = load_legacy_account::<MetadataExtended, LegacyMetadataExtended>(ctx.program_id, &ctx.accounts.legacy_metadata_extended, LEGACY_METADATA_EXTENDED_SIZE)?;
+        let pool = &ctx.accounts.pool;
+        if pool.legacy_pool == Pubkey::default() || legacy.pool != pool.legacy_pool {
+            return Err(PoolError::InvalidPoolAccount.into());
+        }
+        let legacy_key = Pubkey::create_program_address(&[ctx.accounts.mint.key.as_ref(), legacy.pool.as_ref(), ctx.program_id.as_ref(), &[legacy.bump]], ctx.program_id)
+            .map_err(|_| PoolError::InvalidMetadata)?;
+        if legacy_key != *ctx.accounts.legacy_metadata_extended.key {
+            return Err(PoolError::InvalidMetadata.into());
+        }
+        let metadata_extended = &mut ctx.accounts.metadata_extended;
+        metadata_extended.version = METADATA_EXTENDED_VERSION;
+        metadata_extended.pool = pool.key();
+        metadata_extended.max_price = legacy.max_price;
+        metadata_extended.bump = _bump;
+        metadata_extended.mint_index = 0;
+        metadata_extended.hidden = false;
+        metadata_extended.legacy = true;
+        metadata_extended.minter = *ctx.accounts.payer.key;
+        close_account(&ctx.accounts.legacy_metadata_extended, &ctx.accounts.payer)
+    }
+}
+
+#[derive(Accounts)]
+pub struct CloseSaleManager<'info> {
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
+
+    #[account(has_one=owner)]
+    pool : ProgramAccount<'info,Pool>,
+
+    #[account(owner=spl_token::id())]
+    nft_mint : AccountInfo<'info>,
+
+    #[account(mut,close=recipient,seeds=[pool.key().as_ref(),(*nft_mint.key).as_ref()],bump=sale_manager.bump)]
+    sale_manager : ProgramAccount<'info,SaleManager>,
+
+    sale_pot : AccountInfo<'info>,
+
+    #[account(mut)]
+    recipient : AccountInfo<'info>,
+
+    rent : Sysvar<'info,Rent>,
+}
+
+#[derive(Accounts)]
+pub struct CloseMetadataExtended<'info> {
+    #[account(signer)]
+    authority : AccountInfo<'info>,
+
+    pool : AccountInfo<'info>,
+
+    #[account(owner=spl_token::id())]
+    mint : AccountInfo<'info>,
+
+    #[account(mut,close=minter,seeds=[(*mint.key).as_ref(),(*pool.key).as_ref(),program_id.as_ref()], bump=metadata_extended.bump)]
+    metadata_extended : ProgramAccount<'info,MetadataExtended>,
+
+    sale_manager : AccountInfo<'info>,
+
+    #[account(mut)]
+    minter : AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseClient<'info> {
+    #[account(signer)]
+    authority : AccountInfo<'info>,
+
+    pool : ProgramAccount<'info,Pool>,
+
+    #[account(mut,close=recipient,seeds=[program_id.as_ref(), pool.key().as_ref(), client.owner.key().as_ref()], bump=client.bump)]
+    client : ProgramAccount<'info,Client>,
+
+    #[account(mut)]
+    recipient : AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClosePool<'info> {
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
+
+    #[account(mut,has_one=owner,close=recipient)]
+    pool : ProgramAccount<'info,Pool>,
+
+    #[account(seeds=[b"treasury".as_ref(), pool.key().as_ref()], bump=pool.treasury_bump)]
+    treasury : AccountInfo<'info>,
+
+    #[account(mut)]
+    pool_authority : AccountInfo<'info>,
+
+    #[account(mut)]
+    pool_registry : AccountInfo<'info>,
+
+    #[account(mut)]
+    recipient : AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClosePoolRegistry<'info> {
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
+
+    #[account(mut,has_one=owner,close=owner,seeds=[b"pool_registry".as_ref(), (*owner.key).as_ref()], bump=pool_registry.bump)]
+    pool_registry : ProgramAccount<'info,PoolRegistry>,
+}
+
+#[derive(Accounts)]
+#[instruction(_bump : u8)]
+pub struct MigrateMetadataExtended<'info> {
+    #[account(mut,signer)]
+    payer : AccountInfo<'info>,
+
+    pool : ProgramAccount<'info,Pool>,
+
+    #[account(owner=spl_token::id())]
+    mint : AccountInfo<'info>,
+
+    #[account(mut)]
+    legacy_metadata_extended : AccountInfo<'info>,
+
+    #[account(init,seeds=[(*mint.key).as_ref(),pool.key().as_ref(),program_id.as_ref()],bump=_bump,payer=payer,space=8+METADATA_EXTENDED_SIZE+METADATA_EXTENDED_PADDING)]
+    metadata_extended : ProgramAccount<'info,MetadataExtended>,
+
+    system_program : Program<'info,System>,
+}
+
+#[derive(Accounts)]
+#[instruction(_bump : u8)]
+pub struct MigrateClient<'info> {
+    #[account(mut,signer)]
+    payer : AccountInfo<'info>,
+
+    pool : ProgramAccount<'info,Pool>,
+
+    bidder : AccountInfo<'info>,
+
+    #[account(mut)]
+    legacy_client : AccountInfo<'info>,
+
+    #[account(init, seeds=[program_id.as_ref(), pool.key().as_ref(), (*bidder.key).as_ref()], bump=_bump, payer=payer, space=8+CLIENT_SIZE+CLIENT_PADDING)]
+    client : ProgramAccount<'info, Client>,
+
+    system_program : Program<'info,System>,
+}
+
+#[derive(Accounts)]
+#[instruction(_bump : u8)]
+pub struct MigratePool<'info> {
+    #[account(mut,signer)]
+    payer : AccountInfo<'info>,
+
+    #[account(mut)]
+    legacy_pool : AccountInfo<'info>,
+
+    #[account(init, seeds=[b"migrated_pool".as_ref(), legacy_pool.key.as_ref()], bump=_bump, payer=payer, space=8+POOL_SIZE+POOL_PADDING)]
+    pool : ProgramAccount<'info,Pool>,
+
+    system_program : Program<'info,System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawFundNative<'info> {
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
+
+    pool : ProgramAccount<'info,Pool>,
+
+    #[account(seeds=[b"config".as_ref()], bump=config.bump)]
+    config : ProgramAccount<'info,ProgramConfig>,
+
+    #[account(mut,seeds=[sale_manager.pool.as_ref(),sale_manager.nft_mint.as_ref()],bump=sale_manager.bump)]
+    sale_manager : ProgramAccount<'info,SaleManager>,
+
+    #[account(mut)]
+    sale_pot : ProgramAccount<'info,SalePot>,
+
+    rent : Sysvar<'info,Rent>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawFund<'info> {
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
+
+    pool : ProgramAccount<'info,Pool>,
+
+    #[account(seeds=[b"config".as_ref()], bump=config.bump)]
+    config : ProgramAccount<'info,ProgramConfig>,
+
+    sale_manager : AccountInfo<'info>,
+
+    #[account(mut)]
+    sale_pot : AccountInfo<'info>,
+
+    #[account(mut,owner=spl_token::id())]
+    pool_pot : AccountInfo<'info>,
+
+    #[account(mut,owner=spl_token::id())]
+    withdraw_pot : AccountInfo<'info>,
+
+    #[account(address=spl_token::id())]
+    token_program : AccountInfo<'info>,     
+}
+
+#[derive(Accounts)]
+pub struct RedeemNft<'info> {
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
+
+    #[account(mut)]
+    pool : ProgramAccount<'info,Pool>,
+
+    #[account(owner=spl_token::id())]
+    nft_mint : AccountInfo<'info>,
+
+    #[account(mut)]
+    metadata : AccountInfo<'info>,
+
+    #[account(mut)]
+    sale_manager : AccountInfo<'info>,
+
+    #[account(mut,owner=spl_token::id())]
+    nft_seller_token : AccountInfo<'info>,
+
+    #[account(mut,owner=spl_token::id())]
+    nft_manager_token : AccountInfo<'info>,  
+
+    #[account(address=metaplex_token_metadata::id())]
+    token_metadata_program : AccountInfo<'info>,
+
+    #[account(address=spl_token::id())]
+    token_program : AccountInfo<'info>,  
+}
+
+#[derive(Accounts)]
+pub struct SellNftNative<'info> {
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
+
+    #[account(mut)]
+    pool : ProgramAccount<'info,Pool>,
+
+    #[account(seeds=[b"config".as_ref()], bump=config.bump)]
+    config : ProgramAccount<'info,ProgramConfig>,
+
+    #[account(owner=spl_token::id())]
+    nft_mint : AccountInfo<'info>,
+
+    #[account(mut)]
+    metadata : AccountInfo<'info>,
+
+    #[account(mut,seeds=[(*nft_mint.key).as_ref(),pool.key().as_ref(),program_id.as_ref()], bump=metadata_extended.bump)]
+    metadata_extended : ProgramAccount<'info, MetadataExtended>,
+
+    #[account(mut,seeds=[pool.key().as_ref(),(*nft_mint.key).as_ref()],bump=sale_manager.bump)]
+    sale_manager : ProgramAccount<'info,SaleManager>,
+
+    #[account(init,payer=owner,space=8+SALE_POT_SIZE+SALE_POT_PADDING)]
+    sale_pot : ProgramAccount<'info,SalePot>,
+
+    #[account(mut,owner=spl_token::id())]
+    nft_seller_token : AccountInfo<'info>,
+
+    #[account(mut,owner=spl_token::id())]
+    nft_manager_token : AccountInfo<'info>,
+
+    #[account(address=metaplex_token_metadata::id())]
+    token_metadata_program : AccountInfo<'info>,
+
+    #[account(address=spl_token::id())]
+    token_program : AccountInfo<'info>,
+
+    system_program : Program<'info,System>,
+}
+
+#[derive(Accounts)]
+pub struct BuyNftNative<'info> {
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
+
+    #[account(mut)]
+    pool : ProgramAccount<'info,Pool>,
+
+    #[account(seeds=[b"config".as_ref()], bump=config.bump)]
+    config : ProgramAccount<'info,ProgramConfig>,
+
+    #[account(owner=spl_token::id())]
+    nft_mint : AccountInfo<'info>,
+
+    #[account(mut)]
+    metadata : AccountInfo<'info>,
+
+    #[account(mut,seeds=[pool.key().as_ref(),(*nft_mint.key).as_ref()],bump=sale_manager.bump)]
+    sale_manager : ProgramAccount<'info,SaleManager>,
+
+    #[account(mut)]
+    sale_pot : ProgramAccount<'info,SalePot>,    
+
+    #[account(mut,owner=spl_token::id())]
+    nft_manager_token : AccountInfo<'info>,
+
+    #[account(mut,owner=spl_token::id())]
+    nft_bidder_token : AccountInfo<'info>,
+
+    #[account(mut)]
+    fee_treasury : AccountInfo<'info>,
+
+    #[account(address=metaplex_token_metadata::id())]
+    token_metadata_program : AccountInfo<'info>,
+
+    #[account(address=spl_token::id())]
+    token_program : AccountInfo<'info>,
+
+    system_program : Program<'info,System>,
+}
+
+#[derive(Accounts)]
+pub struct BuyNft<'info> {
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
+
+    #[account(mut)]
+    pool : ProgramAccount<'info,Pool>,
+
+    #[account(seeds=[b"config".as_ref()], bump=config.bump)]
+    config : ProgramAccount<'info,ProgramConfig>,
+
+    #[account(owner=spl_token::id())]
+    nft_mint : AccountInfo<'info>,
+
+    #[account(mut)]
+    metadata : AccountInfo<'info>,
+
+    #[account(mut,seeds=[pool.key().as_ref(),(*nft_mint.key).as_ref()],bump=sale_manager.bump)]
+    sale_manager : ProgramAccount<'info,SaleManager>,
+
+    #[account(mut)]
+    sale_pot : ProgramAccount<'info,SalePot>,    
+
+    #[account(mut,owner=spl_token::id())]
+    nft_manager_token : AccountInfo<'info>,
+
+    #[account(mut,owner=spl_token::id())]
+    nft_bidder_token : AccountInfo<'info>,
+
+    #[account(mut,owner=spl_token::id())]
+    manager_pot : AccountInfo<'info>,
+
+    #[account(mut,owner=spl_token::id())]
+    bidder_token : AccountInfo<'info>,
+
+    #[account(mut,owner=spl_token::id())]
+    treasury_token : AccountInfo<'info>,
+
+    #[account(address=metaplex_token_metadata::id())]
+    token_metadata_program : AccountInfo<'info>,
+
+    #[account(address=spl_token::id())]
+    token_program : AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SellNft<'info> {
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
+
+    #[account(mut)]
+    pool : ProgramAccount<'info,Pool>,
+
+    #[account(seeds=[b"config".as_ref()], bump=config.bump)]
+    config : ProgramAccount<'info,ProgramConfig>,
+
+    #[account(owner=spl_token::id())]
+    nft_mint : AccountInfo<'info>,
+
+    #[account(mut)]
+    metadata : AccountInfo<'info>,
+
+    #[account(mut,seeds=[(*nft_mint.key).as_ref(),pool.key().as_ref(),program_id.as_ref()], bump=metadata_extended.bump)]
+    metadata_extended : ProgramAccount<'info, MetadataExtended>,
+
+    #[account(mut,seeds=[pool.key().as_ref(),(*nft_mint.key).as_ref()],bump=sale_manager.bump)]
+    sale_manager : ProgramAccount<'info,SaleManager>,
+
+    #[account(init,payer=owner,space=8+SALE_POT_SIZE+SALE_POT_PADDING)]
+    sale_pot : ProgramAccount<'info,SalePot>,
+
+    #[account(mut,owner=spl_token::id())]
+    nft_seller_token : AccountInfo<'info>,
+
+    #[account(mut,owner=spl_token::id())]
+    nft_manager_token : AccountInfo<'info>,
+
+    #[account(mut,owner=spl_token::id())]
+    manager_pot : AccountInfo<'info>,
+
+    #[account(address=metaplex_token_metadata::id())]
+    token_metadata_program : AccountInfo<'info>,
+
+    #[account(address=spl_token::id())]
+    token_program : AccountInfo<'info>,
+
+    system_program : Program<'info,System>,
+}
+
+#[derive(Accounts)]
+#[instruction(_bump : u8)]
+pub struct InitSaleManager<'info> {
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
+
+    pool : ProgramAccount<'info,Pool>,
+
+    #[account(owner=spl_token::id())]
+    nft_mint : AccountInfo<'info>,
+
+    #[account(init,seeds=[pool.key().as_ref(),(*nft_mint.key).as_ref()],bump=_bump,payer=owner,space=8+MAX_SALE_MANAGER_SIZE+SALE_MANAGER_PADDING)]
+    sale_manager : ProgramAccount<'info,SaleManager>,
+
+    system_program : Program<'info,System>
+}
+
+#[derive(Accounts)]
+pub struct CancelPoolChange<'info> {
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
+
+    #[account(has_one=owner)]
+    pool : ProgramAccount<'info, Pool>,
+
+    #[account(mut, has_one=pool, has_one=payer, close=payer)]
+    queued_change : ProgramAccount<'info, QueuedChange>,
+
+    #[account(mut)]
+    payer : AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExecutePoolChange<'info> {
+    #[account(mut)]
+    pool : ProgramAccount<'info, Pool>,
+
+    #[account(mut, has_one=pool, has_one=payer, close=payer)]
+    queued_change : ProgramAccount<'info, QueuedChange>,
+
+    #[account(mut)]
+    payer : AccountInfo<'info>,
+
+    #[account(mut)]
+    target : AccountInfo<'info>,
+
+    clock : Sysvar<'info,Clock>,
+}
+
+#[derive(Accounts)]
+pub struct QueuePoolChange<'info> {
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
+
+    pool : ProgramAccount<'info, Pool>,
+
+    pool_role : AccountInfo<'info>,
+
+    #[account(init, payer=owner, space=8+QUEUED_CHANGE_SIZE)]
+    queued_change : ProgramAccount<'info, QueuedChange>,
+
+    clock : Sysvar<'info,Clock>,
+
+    system_program : Program<'info,System>,
+}
+
+#[derive(Accounts)]
+pub struct SetTimelockDelay<'info> {
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
+
+    #[account(mut, has_one=owner)]
+    pool : ProgramAccount<'info, Pool>,
+}
+
+#[derive(Accounts)]
+pub struct SetMarketplaceFee<'info> {
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
+
+    #[account(mut)]
+    pool : ProgramAccount<'info, Pool>,
+
+    pool_role : AccountInfo<'info>,
+
+    fee_treasury : AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RemovePaymentMint<'info> {
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
+
+    #[account(mut)]
+    pool : ProgramAccount<'info, Pool>,
+
+    pool_role : AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AddPaymentMint<'info> {
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
+
+    #[account(mut)]
+    pool : ProgramAccount<'info, Pool>,
+
+    pool_role : AccountInfo<'info>,
+
+    #[account(owner=spl_token::id())]
+    payment_mint : AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetMaxPrice<'info> {
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
+
+    pool : ProgramAccount<'info, Pool>,
+
+    pool_role : AccountInfo<'info>,
+
+    #[account(owner=spl_token::id())]
+    mint : AccountInfo<'info>,
+
+    #[account(mut,seeds=[(*mint.key).as_ref(),pool.key().as_ref(),program_id.as_ref()], bump=metadata_extended.bump)]
+    metadata_extended : ProgramAccount<'info, MetadataExtended>,
+}
+
+#[derive(Accounts)]
+#[instruction(_bump : u8)]
+pub struct MintNftPublic<'info> {
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
+
+    #[account(mut)]
+    pool : ProgramAccount<'info,Pool>,
+
+    #[account(seeds=[b"config".as_ref()], bump=config.bump)]
+    config : ProgramAccount<'info,ProgramConfig>,
+
+    #[account(mut)]
+    mint_record : AccountInfo<'info>,
+
+    #[account(mut,owner=spl_token::id())]
+    mint : AccountInfo<'info>,
+
+    #[account(mut,owner=spl_token::id())]
+    token_account : AccountInfo<'info>,
+
+    #[account(mut)]
+    metadata : AccountInfo<'info>,
+
+    #[account(mut)]
+    master_edition : AccountInfo<'info>,
+
+    #[account(init,seeds=[(*mint.key).as_ref(),pool.key().as_ref(),program_id.as_ref()],bump=_bump,payer=owner,space=8+METADATA_EXTENDED_SIZE+METADATA_EXTENDED_PADDING,)]
+    metadata_extended : ProgramAccount<'info,MetadataExtended>,
+
+    #[account(mut, seeds=[b"pool_signer".as_ref(), pool.key().as_ref()], bump=pool.signer_bump)]
+    pool_signer : AccountInfo<'info>,
+
+    collection_mint : AccountInfo<'info>,
+
+    collection_metadata : AccountInfo<'info>,
+
+    collection_master_edition : AccountInfo<'info>,
+
+    #[account(mut)]
+    config_lines : AccountInfo<'info>,
+
+    #[account(mut)]
+    treasury : AccountInfo<'info>,
+
+    #[account(mut)]
+    payer_token : AccountInfo<'info>,
+
+    #[account(mut)]
+    treasury_token : AccountInfo<'info>,
+
+    #[account(address=metaplex_token_metadata::id())]
+    token_metadata_program : AccountInfo<'info>,
+
+    #[account(address=spl_token::id())]
+    token_program : AccountInfo<'info>,
+
+    system_program : Program<'info,System>,
+
+    rent : Sysvar<'info,Rent>,
+
+    clock : Sysvar<'info,Clock>,
+}
+
+#[derive(Accounts)]
+#[instruction(_bump : u8)]
+pub struct MintNftWithProof<'info> {
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
+
+    #[account(mut)]
+    pool : ProgramAccount<'info,Pool>,
+
+    #[account(seeds=[b"config".as_ref()], bump=config.bump)]
+    config : ProgramAccount<'info,ProgramConfig>,
+
+    #[account(mut)]
+    mint_record : AccountInfo<'info>,
+
+    #[account(mut,owner=spl_token::id())]
+    mint : AccountInfo<'info>,
+
+    #[account(mut,owner=spl_token::id())]
+    token_account : AccountInfo<'info>,
+
+    #[account(mut)]
+    metadata : AccountInfo<'info>,
+
+    #[account(mut)]
+    master_edition : AccountInfo<'info>,
+
+    #[account(init,seeds=[(*mint.key).as_ref(),pool.key().as_ref(),program_id.as_ref()],bump=_bump,payer=owner,space=8+METADATA_EXTENDED_SIZE+METADATA_EXTENDED_PADDING,)]
+    metadata_extended : ProgramAccount<'info,MetadataExtended>,
+
+    #[account(mut, seeds=[b"pool_signer".as_ref(), pool.key().as_ref()], bump=pool.signer_bump)]
+    pool_signer : AccountInfo<'info>,
+
+    collection_mint : AccountInfo<'info>,
+
+    collection_metadata : AccountInfo<'info>,
+
+    collection_master_edition : AccountInfo<'info>,
+
+    #[account(mut)]
+    config_lines : AccountInfo<'info>,
+
+    #[account(mut)]
+    treasury : AccountInfo<'info>,
+
+    #[account(mut)]
+    payer_token : AccountInfo<'info>,
+
+    #[account(mut)]
+    treasury_token : AccountInfo<'info>,
+
+    #[account(address=metaplex_token_metadata::id())]
+    token_metadata_program : AccountInfo<'info>,
+
+    #[account(address=spl_token::id())]
+    token_program : AccountInfo<'info>,
+
+    system_program : Program<'info,System>,
+
+    rent : Sysvar<'info,Rent>,
+
+    clock : Sysvar<'info,Clock>,
+}
+
+#[derive(Accounts)]
+pub struct MintNftBatch<'info> {
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
+
+    #[account(mut)]
+    pool : ProgramAccount<'info,Pool>,
+
+    #[account(seeds=[b"config".as_ref()], bump=config.bump)]
+    config : ProgramAccount<'info,ProgramConfig>,
+
+    #[account(mut,has_one=owner,seeds=[program_id.as_ref(), pool.key().as_ref(), (*owner.key).as_ref()], bump=client.bump)]
+    client : ProgramAccount<'info,Client>,
+
+    #[account(mut, seeds=[b"pool_signer".as_ref(), pool.key().as_ref()], bump=pool.signer_bump)]
+    pool_signer : AccountInfo<'info>,
+
+    collection_mint : AccountInfo<'info>,
+
+    collection_metadata : AccountInfo<'info>,
+
+    collection_master_edition : AccountInfo<'info>,
+
+    #[account(mut)]
+    config_lines : AccountInfo<'info>,
+
+    #[account(mut)]
+    treasury : AccountInfo<'info>,
+
+    #[account(mut)]
+    payer_token : AccountInfo<'info>,
+
+    #[account(mut)]
+    treasury_token : AccountInfo<'info>,
+
+    #[account(address=metaplex_token_metadata::id())]
+    token_metadata_program : AccountInfo<'info>,
+
+    #[account(address=spl_token::id())]
+    token_program : AccountInfo<'info>,
+
+    system_program : Program<'info,System>,
+
+    rent : Sysvar<'info,Rent>,
+
+    clock : Sysvar<'info,Clock>,
+}
+
+#[derive(Accounts)]
+#[instruction(_bump : u8)]
+pub struct MintNft<'info> {
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
+
+    #[account(mut)]
+    pool : ProgramAccount<'info,Pool>,
+
+    #[account(seeds=[b"config".as_ref()], bump=config.bump)]
+    config : ProgramAccount<'info,ProgramConfig>,
+
+    #[account(mut,has_one=owner,seeds=[program_id.as_ref(), pool.key().as_ref(), (*owner.key).as_ref()], bump=client.bump)]
+    client : ProgramAccount<'info,Client>,
+
+    #[account(mut,owner=spl_token::id())]
+    mint : AccountInfo<'info>,
+
+    #[account(mut,owner=spl_token::id())]
+    token_account : AccountInfo<'info>,
+
+    #[account(mut)]
+    metadata : AccountInfo<'info>,
+
+    #[account(mut)]
+    master_edition : AccountInfo<'info>,
+
+    #[account(init,seeds=[(*mint.key).as_ref(),pool.key().as_ref(),program_id.as_ref()],bump=_bump,payer=owner,space=8+METADATA_EXTENDED_SIZE+METADATA_EXTENDED_PADDING,)]
+    metadata_extended : ProgramAccount<'info,MetadataExtended>,
+
+    #[account(mut, seeds=[b"pool_signer".as_ref(), pool.key().as_ref()], bump=pool.signer_bump)]
+    pool_signer : AccountInfo<'info>,
+
+    collection_mint : AccountInfo<'info>,
+
+    collection_metadata : AccountInfo<'info>,
+
+    collection_master_edition : AccountInfo<'info>,
+
+    #[account(mut)]
+    config_lines : AccountInfo<'info>,
+
+    #[account(mut)]
+    treasury : AccountInfo<'info>,
+
+    #[account(mut)]
+    payer_token : AccountInfo<'info>,
+
+    #[account(mut)]
+    treasury_token : AccountInfo<'info>,
+
+    #[account(address=metaplex_token_metadata::id())]
+    token_metadata_program : AccountInfo<'info>,
+
+    #[account(address=spl_token::id())]
+    token_program : AccountInfo<'info>,
+
+    system_program : Program<'info,System>,
+
+    rent : Sysvar<'info,Rent>,
+
+    clock : Sysvar<'info,Clock>,
+}
+
+#[derive(Accounts)]
+pub struct AddConfigLines<'info> {
+    #[account(mut, has_one=pool)]
+    config_lines : ProgramAccount<'info,ConfigLines>,
+
+    #[account(has_one=owner)]
+    pool : ProgramAccount<'info,Pool>,
+
+    #[account(signer)]
+    owner : AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseConfigLines<'info> {
+    #[account(mut, has_one=pool, close=owner)]
+    config_lines : ProgramAccount<'info,ConfigLines>,
+
+    #[account(mut, has_one=owner)]
+    pool : ProgramAccount<'info,Pool>,
+
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitConfigLines<'info> {
+    #[account(zero)]
+    config_lines : ProgramAccount<'info,ConfigLines>,
+
+    #[account(mut, has_one=owner)]
+    pool : ProgramAccount<'info,Pool>,
+
+    #[account(signer)]
+    owner : AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ReleaseCollection<'info> {
+    #[account(mut, has_one=owner)]
+    pool : ProgramAccount<'info,Pool>,
+
+    #[account(signer)]
+    owner : AccountInfo<'info>,
+
+    #[account(seeds=[b"pool_signer".as_ref(), pool.key().as_ref()], bump=pool.signer_bump)]
+    pool_signer : AccountInfo<'info>,
+
+    #[account(owner=spl_token::id())]
+    collection_mint : AccountInfo<'info>,
+
+    #[account(mut)]
+    collection_metadata : AccountInfo<'info>,
+
+    #[account(address=metaplex_token_metadata::id())]
+    token_metadata_program : AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetCollection<'info> {
+    #[account(mut, has_one=owner)]
+    pool : ProgramAccount<'info,Pool>,
+
+    #[account(signer)]
+    owner : AccountInfo<'info>,
+
+    #[account(seeds=[b"pool_signer".as_ref(), pool.key().as_ref()], bump=pool.signer_bump)]
+    pool_signer : AccountInfo<'info>,
+
+    #[account(owner=spl_token::id())]
+    collection_mint : AccountInfo<'info>,
+
+    #[account(mut)]
+    collection_metadata : AccountInfo<'info>,
+
+    #[account(address=metaplex_token_metadata::id())]
+    token_metadata_program : AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Reveal<'info> {
+    pool : ProgramAccount<'info,Pool>,
 
-        Ok(())
-    }
+    #[account(seeds=[b"pool_signer".as_ref(), pool.key().as_ref()], bump=pool.signer_bump)]
+    pool_signer : AccountInfo<'info>,
+
+    #[account(owner=spl_token::id())]
+    mint : AccountInfo<'info>,
+
+    #[account(mut,seeds=[(*mint.key).as_ref(),pool.key().as_ref(),program_id.as_ref()], bump=metadata_extended.bump)]
+    metadata_extended : ProgramAccount<'info,MetadataExtended>,
+
+    #[account(mut)]
+    metadata : AccountInfo<'info>,
+
+    #[account(owner=spl_token::id())]
+    holder_token : AccountInfo<'info>,
+
+    #[account(address=metaplex_token_metadata::id())]
+    token_metadata_program : AccountInfo<'info>,
 }
 
 #[derive(Accounts)]
-pub struct WithdrawFund<'info> {
-    #[account(mut,signer)]
+pub struct SetHiddenMode<'info> {
+    #[account(mut, has_one=owner)]
+    pool : ProgramAccount<'info,Pool>,
+
+    #[account(signer)]
     owner : AccountInfo<'info>,
 
+    #[account(has_one=pool)]
+    config_lines : ProgramAccount<'info,ConfigLines>,
+}
+
+#[derive(Accounts)]
+pub struct SetEditionMint<'info> {
     #[account(mut)]
-    sale_manager : ProgramAccount<'info,SaleManager>,
+    pool : ProgramAccount<'info,Pool>,
+
+    #[account(signer)]
+    owner : AccountInfo<'info>,
+
+    pool_role : AccountInfo<'info>,
 
+    #[account(seeds=[b"pool_signer".as_ref(), pool.key().as_ref()], bump=pool.signer_bump)]
+    pool_signer : AccountInfo<'info>,
+
+    #[account(owner=spl_token::id())]
+    edition_mint : AccountInfo<'info>,
+
+    #[account(owner=spl_token::id())]
+    edition_token : AccountInfo<'info>,
+
+    edition_metadata : AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClearEditionMint<'info> {
     #[account(mut)]
-    sale_pot : ProgramAccount<'info,SalePot>,
+    pool : ProgramAccount<'info,Pool>,
+
+    #[account(signer)]
+    owner : AccountInfo<'info>,
+
+    pool_role : AccountInfo<'info>,
+
+    #[account(seeds=[b"pool_signer".as_ref(), pool.key().as_ref()], bump=pool.signer_bump)]
+    pool_signer : AccountInfo<'info>,
 
     #[account(mut,owner=spl_token::id())]
-    pool_pot : AccountInfo<'info>,
+    edition_token : AccountInfo<'info>,
 
     #[account(mut,owner=spl_token::id())]
-    withdraw_pot : AccountInfo<'info>,
+    owner_token : AccountInfo<'info>,
 
     #[account(address=spl_token::id())]
-    token_program : AccountInfo<'info>,     
+    token_program : AccountInfo<'info>,
 }
 
 #[derive(Accounts)]
-pub struct RedeemNft<'info> {
+pub struct SetMaxSupply<'info> {
+    #[account(mut)]
+    pool : ProgramAccount<'info,Pool>,
+
     #[account(mut,signer)]
     owner : AccountInfo<'info>,
 
-    pool : ProgramAccount<'info,Pool>,
+    pool_role : AccountInfo<'info>,
+}
 
-    #[account(owner=spl_token::id())]
-    nft_mint : AccountInfo<'info>,
+#[derive(Accounts)]
+pub struct WithdrawTreasury<'info> {
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
 
     #[account(mut)]
-    metadata : AccountInfo<'info>,
+    pool : ProgramAccount<'info,Pool>,
 
-    #[account(mut,seeds=[pool.key().as_ref(),(*nft_mint.key).as_ref()],bump=sale_manager.bump)]
-    sale_manager : ProgramAccount<'info,SaleManager>,
+    pool_role : AccountInfo<'info>,
 
-    #[account(mut,owner=spl_token::id())]
-    nft_seller_token : AccountInfo<'info>,
+    #[account(mut, seeds=[b"treasury".as_ref(), pool.key().as_ref()], bump=pool.treasury_bump)]
+    treasury : AccountInfo<'info>,
 
-    #[account(mut,owner=spl_token::id())]
-    nft_manager_token : AccountInfo<'info>,  
+    #[account(mut)]
+    treasury_token : AccountInfo<'info>,
 
-    #[account(address=metaplex_token_metadata::id())]
-    token_metadata_program : AccountInfo<'info>,
+    #[account(mut)]
+    destination : AccountInfo<'info>,
 
     #[account(address=spl_token::id())]
-    token_program : AccountInfo<'info>,  
+    token_program : AccountInfo<'info>,
+
+    system_program : Program<'info,System>,
 }
 
 #[derive(Accounts)]
-pub struct BuyNft<'info> {
+pub struct SetMintPrice<'info> {
+    #[account(mut)]
+    pool : ProgramAccount<'info,Pool>,
+
     #[account(mut,signer)]
     owner : AccountInfo<'info>,
 
+    pool_role : AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetPublicMint<'info> {
+    #[account(mut)]
     pool : ProgramAccount<'info,Pool>,
 
-    #[account(owner=spl_token::id())]
-    nft_mint : AccountInfo<'info>,
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
+
+    pool_role : AccountInfo<'info>,
+}
 
+#[derive(Accounts)]
+pub struct SetMintTiers<'info> {
     #[account(mut)]
-    metadata : AccountInfo<'info>,
+    pool : ProgramAccount<'info,Pool>,
 
-    #[account(mut,seeds=[pool.key().as_ref(),(*nft_mint.key).as_ref()],bump=sale_manager.bump)]
-    sale_manager : ProgramAccount<'info,SaleManager>,
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
+
+    pool_role : AccountInfo<'info>,
+}
 
+#[derive(Accounts)]
+pub struct SetMintPhases<'info> {
     #[account(mut)]
-    sale_pot : ProgramAccount<'info,SalePot>,    
+    pool : ProgramAccount<'info,Pool>,
 
-    #[account(mut,owner=spl_token::id())]
-    nft_manager_token : AccountInfo<'info>,
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
 
-    #[account(mut,owner=spl_token::id())]
-    nft_bidder_token : AccountInfo<'info>,
+    pool_role : AccountInfo<'info>,
+}
 
-    #[account(mut,owner=spl_token::id())]
-    manager_pot : AccountInfo<'info>,
+#[derive(Accounts)]
+pub struct SetMerkleRoot<'info> {
+    #[account(mut)]
+    pool : ProgramAccount<'info,Pool>,
 
-    #[account(mut,owner=spl_token::id())]
-    bidder_token : AccountInfo<'info>,
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
 
-    #[account(address=metaplex_token_metadata::id())]
-    token_metadata_program : AccountInfo<'info>,
+    pool_role : AccountInfo<'info>,
+}
 
-    #[account(address=spl_token::id())]
-    token_program : AccountInfo<'info>,
+#[derive(Accounts)]
+pub struct ControlPresaleLive<'info> {
+    #[account(mut)]
+    pool : ProgramAccount<'info,Pool>,
+
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
+
+    pool_role : AccountInfo<'info>,
 }
 
 #[derive(Accounts)]
-pub struct SellNft<'info> {
+pub struct UpdateWhitelistBatch<'info>{
+    pool : ProgramAccount<'info,Pool>,
+
     #[account(mut,signer)]
     owner : AccountInfo<'info>,
 
+    pool_role : AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetWhitelistBatch<'info>{
     pool : ProgramAccount<'info,Pool>,
 
-    #[account(owner=spl_token::id())]
-    nft_mint : AccountInfo<'info>,
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
 
-    #[account(mut)]
-    metadata : AccountInfo<'info>,
+    pool_role : AccountInfo<'info>,
 
-    #[account(mut,seeds=[(*nft_mint.key).as_ref(),pool.key().as_ref(),program_id.as_ref()], bump=metadata_extended.bump)]
-    metadata_extended : ProgramAccount<'info, MetadataExtended>,
+    system_program : Program<'info,System>,
 
-    #[account(mut,seeds=[pool.key().as_ref(),(*nft_mint.key).as_ref()],bump=sale_manager.bump)]
-    sale_manager : ProgramAccount<'info,SaleManager>,
+    rent : Sysvar<'info,Rent>,
+}
 
-    #[account(init,payer=owner,space=8+SALE_POT_SIZE)]
-    sale_pot : ProgramAccount<'info,SalePot>,
+#[derive(Accounts)]
+pub struct UpdateWhitelist<'info>{
+    #[account(mut, seeds=[program_id.as_ref(), pool.key().as_ref(), client.owner.key().as_ref()], bump=client.bump)]
+    client : ProgramAccount<'info, Client>,
 
-    #[account(mut,owner=spl_token::id())]
-    nft_seller_token : AccountInfo<'info>,
+    pool : ProgramAccount<'info,Pool>,
 
-    #[account(mut,owner=spl_token::id())]
-    nft_manager_token : AccountInfo<'info>,
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
 
-    #[account(mut,owner=spl_token::id())]
-    manager_pot : AccountInfo<'info>,
+    pool_role : AccountInfo<'info>,
+}
 
-    #[account(address=metaplex_token_metadata::id())]
-    token_metadata_program : AccountInfo<'info>,
+#[derive(Accounts)]
+#[instruction(_bump : u8)]
+pub struct SetWhitelist<'info>{
+    #[account(init, seeds=[program_id.as_ref(), pool.key().as_ref(), (*bidder.key).as_ref()], bump=_bump, payer=owner, space=8+CLIENT_SIZE+CLIENT_PADDING)]
+    client : ProgramAccount<'info, Client>,
 
-    #[account(address=spl_token::id())]
-    token_program : AccountInfo<'info>,
+    pool : ProgramAccount<'info,Pool>,
+
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
+
+    pool_role : AccountInfo<'info>,
+
+    bidder : AccountInfo<'info>,
 
     system_program : Program<'info,System>,
 }
 
+#[derive(Accounts)]
+pub struct ClosePoolRole<'info>{
+    #[account(mut, has_one=pool, close=owner, seeds=[b"pool_role".as_ref(), pool.key().as_ref(), pool_role.member.as_ref()], bump=pool_role.bump)]
+    pool_role : ProgramAccount<'info,PoolRole>,
+
+    #[account(mut, has_one=owner)]
+    pool : ProgramAccount<'info,Pool>,
+
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePoolRole<'info>{
+    #[account(mut, has_one=pool, seeds=[b"pool_role".as_ref(), pool.key().as_ref(), pool_role.member.as_ref()], bump=pool_role.bump)]
+    pool_role : ProgramAccount<'info,PoolRole>,
+
+    #[account(has_one=owner)]
+    pool : ProgramAccount<'info,Pool>,
+
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
+}
+
 #[derive(Accounts)]
 #[instruction(_bump : u8)]
-pub struct InitSaleManager<'info> {
+pub struct SetPoolRole<'info>{
+    #[account(init, seeds=[b"pool_role".as_ref(), pool.key().as_ref(), (*member.key).as_ref()], bump=_bump, payer=owner, space=8+POOL_ROLE_SIZE)]
+    pool_role : ProgramAccount<'info,PoolRole>,
+
+    #[account(mut, has_one=owner)]
+    pool : ProgramAccount<'info,Pool>,
+
     #[account(mut,signer)]
     owner : AccountInfo<'info>,
 
+    member : AccountInfo<'info>,
+
+    system_program : Program<'info,System>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteAuthorityProposal<'info>{
+    #[account(seeds=[b"pool_authority".as_ref(), pool_authority.pool.as_ref()], bump=pool_authority.bump)]
+    pool_authority : ProgramAccount<'info,PoolAuthority>,
+
+    #[account(seeds=[b"authority_signer".as_ref(), pool_authority.key().as_ref()], bump=pool_authority.signer_bump)]
+    authority_signer : AccountInfo<'info>,
+
+    #[account(mut, has_one=pool_authority)]
+    proposal : ProgramAccount<'info,AuthorityProposal>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveAuthorityProposal<'info>{
+    pool_authority : ProgramAccount<'info,PoolAuthority>,
+
+    #[account(mut, has_one=pool_authority)]
+    proposal : ProgramAccount<'info,AuthorityProposal>,
+
+    #[account(signer)]
+    signer : AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreateAuthorityProposal<'info>{
+    pool_authority : ProgramAccount<'info,PoolAuthority>,
+
+    #[account(init, payer=proposer, space=8+AUTHORITY_PROPOSAL_SIZE)]
+    proposal : ProgramAccount<'info,AuthorityProposal>,
+
+    #[account(mut,signer)]
+    proposer : AccountInfo<'info>,
+
+    system_program : Program<'info,System>,
+}
+
+#[derive(Accounts)]
+pub struct SetAuthoritySigners<'info>{
+    #[account(mut, seeds=[b"pool_authority".as_ref(), pool_authority.pool.as_ref()], bump=pool_authority.bump)]
+    pool_authority : ProgramAccount<'info,PoolAuthority>,
+
+    #[account(signer, seeds=[b"authority_signer".as_ref(), pool_authority.key().as_ref()], bump=pool_authority.signer_bump)]
+    authority_signer : AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(_bump : u8)]
+pub struct InitPoolAuthority<'info>{
+    #[account(mut, has_one=owner)]
     pool : ProgramAccount<'info,Pool>,
 
-    #[account(owner=spl_token::id())]
-    nft_mint : AccountInfo<'info>,
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
 
-    #[account(init,seeds=[pool.key().as_ref(),(*nft_mint.key).as_ref()],bump=_bump,payer=owner,space=8+MAX_SALE_MANAGER_SIZE)]
-    sale_manager : ProgramAccount<'info,SaleManager>,
+    #[account(init, seeds=[b"pool_authority".as_ref(), pool.key().as_ref()], bump=_bump, payer=owner, space=8+POOL_AUTHORITY_SIZE)]
+    pool_authority : ProgramAccount<'info,PoolAuthority>,
 
-    system_program : Program<'info,System>
+    authority_signer : AccountInfo<'info>,
+
+    system_program : Program<'info,System>,
 }
 
 #[derive(Accounts)]
-pub struct SetMaxPrice<'info> {
+pub struct CancelPoolOwnerProposal<'info>{
+    #[account(mut, has_one=owner)]
+    pool : ProgramAccount<'info,Pool>,
+
     #[account(mut,signer)]
     owner : AccountInfo<'info>,
+}
 
-    #[account(has_one=owner)]
-    pool : ProgramAccount<'info, Pool>,
-
-    #[account(owner=spl_token::id())]
-    mint : AccountInfo<'info>,
+#[derive(Accounts)]
+pub struct AcceptPoolOwner<'info>{
+    #[account(mut)]
+    pool : ProgramAccount<'info,Pool>,
 
-    #[account(mut,seeds=[(*mint.key).as_ref(),pool.key().as_ref(),program_id.as_ref()], bump=metadata_extended.bump)]
-    metadata_extended : ProgramAccount<'info, MetadataExtended>,
+    #[account(mut,signer)]
+    new_owner : AccountInfo<'info>,
 }
 
 #[derive(Accounts)]
-#[instruction(_bump : u8)]
-pub struct MintNft<'info> {
+pub struct ProposePoolOwner<'info>{
+    #[account(mut, has_one=owner)]
+    pool : ProgramAccount<'info,Pool>,
+
     #[account(mut,signer)]
     owner : AccountInfo<'info>,
 
+    new_owner : AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetPoolPaused<'info>{
+    #[account(mut)]
     pool : ProgramAccount<'info,Pool>,
 
-    #[account(mut,has_one=owner,seeds=[program_id.as_ref(), pool.key().as_ref(), (*owner.key).as_ref()], bump=client.bump)]
-    client : ProgramAccount<'info,Client>,
+    #[account(mut,signer)]
+    owner : AccountInfo<'info>,
 
-    #[account(mut,owner=spl_token::id())]
-    mint : AccountInfo<'info>,
+    pool_role : AccountInfo<'info>,
+}
 
-    #[account(mut,owner=spl_token::id())]
-    token_account : AccountInfo<'info>,
+#[derive(Accounts)]
+pub struct SetProgramPaused<'info>{
+    #[account(mut, has_one=admin, seeds=[b"config".as_ref()], bump=config.bump)]
+    config : ProgramAccount<'info,ProgramConfig>,
 
-    #[account(mut)]
-    metadata : AccountInfo<'info>,
+    #[account(signer)]
+    admin : AccountInfo<'info>,
+}
 
-    #[account(mut)]
-    master_edition : AccountInfo<'info>,
+#[derive(Accounts)]
+#[instruction(_bump : u8)]
+pub struct InitProgramConfig<'info>{
+    #[account(init, seeds=[b"config".as_ref()], bump=_bump, payer=admin, space=8+PROGRAM_CONFIG_SIZE)]
+    config : ProgramAccount<'info,ProgramConfig>,
 
-    #[account(init,seeds=[(*mint.key).as_ref(),pool.key().as_ref(),program_id.as_ref()],bump=_bump,payer=owner,space=8+METADATA_EXTENDED_SIZE,)]
-    metadata_extended : ProgramAccount<'info,MetadataExtended>,
+    #[account(mut,signer)]
+    admin : AccountInfo<'info>,
 
-    #[account(address=metaplex_token_metadata::id())]
-    token_metadata_program : AccountInfo<'info>,
+    #[account(address=*program_id)]
+    program : AccountInfo<'info>,
 
-    #[account(address=spl_token::id())]
-    token_program : AccountInfo<'info>,
+    program_data : AccountInfo<'info>,
 
     system_program : Program<'info,System>,
-
-    rent : Sysvar<'info,Rent>,
 }
 
 #[derive(Accounts)]
-pub struct ControlPresaleLive<'info> {
+pub struct SetAuthority<'info>{
     #[account(mut, has_one=owner)]
     pool : ProgramAccount<'info,Pool>,
 
     #[account(mut,signer)]
     owner : AccountInfo<'info>,
-}
-
-#[derive(Accounts)]
-pub struct UpdateWhitelist<'info>{
-    #[account(mut, seeds=[program_id.as_ref(), pool.key().as_ref(), client.owner.key().as_ref()], bump=client.bump)]
-    client : ProgramAccount<'info, Client>,
-
-    #[account(has_one=owner)]
-    pool : ProgramAccount<'info,Pool>,
 
     #[account(mut,signer)]
-    owner : AccountInfo<'info>,
+    new_owner : AccountInfo<'info>,
 }
 
 #[derive(Accounts)]
 #[instruction(_bump : u8)]
-pub struct SetWhitelist<'info>{
-    #[account(init, seeds=[program_id.as_ref(), pool.key().as_ref(), (*bidder.key).as_ref()], bump=_bump, payer=owner, space=8+CLIENT_SIZE)]
-    client : ProgramAccount<'info, Client>,
-
-    #[account(has_one=owner)]
+pub struct InitRegisteredPool<'info>{
+    #[account(init, seeds=[b"pool".as_ref(), (*owner.key).as_ref(), &pool_registry.count.to_le_bytes()], bump=_bump, payer=owner, space=8+POOL_SIZE+POOL_PADDING)]
     pool : ProgramAccount<'info,Pool>,
 
+    #[account(mut, has_one=owner, seeds=[b"pool_registry".as_ref(), (*owner.key).as_ref()], bump=pool_registry.bump)]
+    pool_registry : ProgramAccount<'info,PoolRegistry>,
+
     #[account(mut,signer)]
     owner : AccountInfo<'info>,
 
-    bidder : AccountInfo<'info>,
+    #[account(owner=spl_token::id())]
+    sale_mint : AccountInfo<'info>,
 
     system_program : Program<'info,System>,
 }
 
 #[derive(Accounts)]
-pub struct SetAuthority<'info>{
-    #[account(mut, has_one=owner)]
-    pool : ProgramAccount<'info,Pool>,
+#[instruction(_bump : u8)]
+pub struct InitPoolRegistry<'info>{
+    #[account(init, seeds=[b"pool_registry".as_ref(), (*owner.key).as_ref()], bump=_bump, payer=owner, space=8+POOL_REGISTRY_SIZE)]
+    pool_registry : ProgramAccount<'info,PoolRegistry>,
 
     #[account(mut,signer)]
     owner : AccountInfo<'info>,
 
-    #[account(mut)]
-    new_owner : AccountInfo<'info>,
+    system_program : Program<'info,System>,
 }
 
 #[derive(Accounts)]
 pub struct InitPool<'info>{
-    #[account(init, payer=owner, space=8+POOL_SIZE)]
+    #[account(init, payer=owner, space=8+POOL_SIZE+POOL_PADDING)]
     pool : ProgramAccount<'info,Pool>,
 
     #[account(mut,signer)]
@@ -820,18 +3425,185 @@ pub struct InitPool<'info>{
 
 #[account]
 pub struct Pool{
+    pub version : u8,
     pub owner : Pubkey,
     pub presale_live : bool,
     pub sale_mint : Pubkey,
+    pub pending_owner : Pubkey,
+    pub paused : bool,
+    pub pool_registry : Pubkey,
+    pub registry_index : u64,
+    pub bump : u8,
+    pub active_listings : u64,
+    pub fee_basis_points : u16,
+    pub fee_treasury : Pubkey,
+    pub timelock_delay : i64,
+    pub merkle_root : [u8; 32],
+    pub public_mint : bool,
+    pub wallet_limit : u64,
+    pub mint_price : u64,
+    pub mint_price_native : bool,
+    pub treasury_bump : u8,
+    pub max_supply : u64,
+    pub minted : u64,
+    pub signer_bump : u8,
+    pub collection_mint : Pubkey,
+    pub edition_mint : Pubkey,
+    pub reveal_root : [u8; 32],
+    // The config lines every mint draws from; the latest init_config_lines.
+    pub config_lines : Pubkey,
+    // Set on a pool created by migrate_pool. Listings made before the
+    // migration are still keyed on this address.
+    pub legacy_pool : Pubkey,
+    // Accounts and funds that must be closed or withdrawn before close_pool.
+    // treasury_balance counts sale_mint tokens paid into the treasury.
+    pub role_count : u64,
+    pub config_lines_count : u64,
+    pub treasury_balance : u64,
+    // Variable-length fields stay below the header. Add new fixed-size fields
+    // above this line and bump POOL_VERSION.
+    pub payment_mints : Vec<Pubkey>,
+    pub phases : Vec<MintPhase>,
+    pub phase_prices : Vec<u64>,
+    pub hidden_name : String,
+    pub hidden_uri : String,
+    pub tiers : Vec<MintTier>,
+}
+
+// Header of a config lines account. The lines themselves are stored as fixed
+// CONFIG_LINE_SIZE slots right after it, which keeps this struct small to
+// deserialize no matter how many lines the pool loads.
+#[account]
+pub struct ConfigLines{
+    pub pool : Pubkey,
+    pub symbol : String,
+    pub seller_fee_basis_points : u16,
+    pub creators : Vec<Creator>,
+    pub is_mutable : bool,
+    pub random : bool,
+    pub max_lines : u32,
+    pub loaded : u32,
+    pub redeemed : u32,
+}
+
+#[derive(AnchorSerialize,AnchorDeserialize,Clone)]
+pub struct ConfigLine {
+    pub name : String,
+    pub uri : String,
+}
+
+#[derive(AnchorSerialize,AnchorDeserialize,Clone)]
+pub struct WhitelistEntry {
+    pub bidder : Pubkey,
+    pub amount : u64,
+    pub whitelisted : bool,
+    pub bump : u8,
+    pub tier : u8,
+}
+
+// A zero wallet_cap leaves the tier uncapped, and a zero window
+// (start_ts == end_ts == 0) leaves it open whenever the pool is.
+#[derive(AnchorSerialize,AnchorDeserialize,Clone)]
+pub struct MintTier {
+    pub price : u64,
+    pub wallet_cap : u64,
+    pub start_ts : i64,
+    pub end_ts : i64,
+}
+
+#[derive(AnchorSerialize,AnchorDeserialize,Clone)]
+pub struct MintPhase {
+    pub kind : u8,
+    pub start_ts : i64,
+    pub end_ts : i64,
+}
+
+#[derive(AnchorSerialize,AnchorDeserialize,Clone)]
+pub enum PoolChange {
+    MaxPrice { metadata_extended : Pubkey, max_price : u64 },
+    MarketplaceFee { fee_basis_points : u16, fee_treasury : Pubkey },
+    SaleMint { sale_mint : Pubkey },
+    TimelockDelay { delay : i64 },
+    MintPrice { mint_price : u64, mint_price_native : bool, phase_prices : Vec<u64> },
+    MintTiers { tiers : Vec<MintTier> },
+}
+
+#[account]
+pub struct QueuedChange{
+    pub pool : Pubkey,
+    pub payer : Pubkey,
+    pub change : PoolChange,
+    pub eta : i64,
+}
+
+#[account]
+pub struct PoolRegistry{
+    pub owner : Pubkey,
+    pub count : u64,
+    pub active_pools : u64,
+    pub bump : u8,
+}
+
+#[account]
+pub struct ProgramConfig{
+    pub admin : Pubkey,
+    pub paused : bool,
+    pub bump : u8,
+}
+
+#[account]
+pub struct MintRecord{
+    pub pool : Pubkey,
+    pub owner : Pubkey,
+    pub minted : u64,
+    pub bump : u8,
+}
+
+#[account]
+pub struct PoolRole{
+    pub pool : Pubkey,
+    pub member : Pubkey,
+    pub roles : u8,
+    pub bump : u8,
+}
+
+#[account]
+pub struct PoolAuthority{
+    pub pool : Pubkey,
+    pub signers : Vec<Pubkey>,
+    pub threshold : u8,
+    pub seqno : u32,
+    pub signer_bump : u8,
+    pub bump : u8,
+}
+
+#[derive(AnchorSerialize,AnchorDeserialize,Clone)]
+pub struct ProposalAccount {
+    pub pubkey : Pubkey,
+    pub is_signer : bool,
+    pub is_writable : bool,
+}
+
+#[account]
+pub struct AuthorityProposal{
+    pub pool_authority : Pubkey,
+    pub seqno : u32,
+    pub accounts : Vec<ProposalAccount>,
+    pub data : Vec<u8>,
+    pub approvals : Vec<bool>,
+    pub executed : bool,
 }
 
 #[account]
 pub struct Client{
+    pub version : u8,
     pub owner : Pubkey,
     pub pool : Pubkey,
     pub amount : u64,
     pub whitelisted : bool,
     pub bump : u8,
+    pub tier : u8,
+    pub minted : u64,
 }
 
 #[derive(AnchorSerialize,AnchorDeserialize,Clone)]
@@ -853,13 +3625,21 @@ pub struct Metadata{
 
 #[account]
 pub struct MetadataExtended{
+    pub version : u8,
     pub pool : Pubkey,
     pub max_price : u64,
     pub bump : u8,
+    pub mint_index : u64,
+    pub hidden : bool,
+    pub minter : Pubkey,
+    // Set by migrate_metadata_extended. The NFT was minted before pool NFTs
+    // carried the pool signer as a creator, so only this account vouches for it.
+    pub legacy : bool,
 }
 
 #[account]
 pub struct SaleManager{
+    pub version : u8,
     pub pool : Pubkey,
     pub seller : Pubkey,
     pub nft_mint : Pubkey,
@@ -873,6 +3653,62 @@ pub struct SaleManager{
 
 #[account]
 pub struct SalePot{
+    pub version : u8,
+    pub is_used : bool,
+    pub sale_manager : Pubkey,
+    pub pool_pot : Pubkey,
+    pub price : u64,
+    pub is_primary : bool,
+    pub seller : Pubkey,
+    pub seller_verified : bool,
+    pub seller_fee_basis_points : u16,
+    pub creators : Vec<Creator>,
+    pub is_native : bool,
+    pub payment_mint : Pubkey,
+    pub marketplace_fee_basis_points : u16,
+    pub escrow : u64,
+}
+
+// Layouts written before accounts were versioned. They carry the discriminator
+// of the account type they became and have no version byte or padding.
+#[derive(AnchorSerialize,AnchorDeserialize,Clone)]
+pub struct LegacyPool{
+    pub owner : Pubkey,
+    pub presale_live : bool,
+    pub sale_mint : Pubkey,
+}
+
+#[derive(AnchorSerialize,AnchorDeserialize,Clone)]
+pub struct LegacyClient{
+    pub owner : Pubkey,
+    pub pool : Pubkey,
+    pub amount : u64,
+    pub whitelisted : bool,
+    pub bump : u8,
+}
+
+#[derive(AnchorSerialize,AnchorDeserialize,Clone)]
+pub struct LegacyMetadataExtended{
+    pub pool : Pubkey,
+    pub max_price : u64,
+    pub bump : u8,
+}
+
+#[derive(AnchorSerialize,AnchorDeserialize,Clone)]
+pub struct LegacySaleManager{
+    pub pool : Pubkey,
+    pub seller : Pubkey,
+    pub nft_mint : Pubkey,
+    pub nft_pot : Pubkey,
+    pub sale_pot : Pubkey,
+    pub price : u64,
+    pub sale_state : u8,
+    pub is_primary : bool,
+    pub bump : u8,
+}
+
+#[derive(AnchorSerialize,AnchorDeserialize,Clone)]
+pub struct LegacySalePot{
     pub is_used : bool,
     pub sale_manager : Pubkey,
     pub pool_pot : Pubkey,
@@ -884,6 +3720,26 @@ pub struct SalePot{
     pub creators : Vec<Creator>,
 }
 
+#[event]
+pub struct PoolChangeQueued{
+    pub pool : Pubkey,
+    pub queued_change : Pubkey,
+    pub change : PoolChange,
+    pub eta : i64,
+}
+
+#[event]
+pub struct PoolChangeExecuted{
+    pub pool : Pubkey,
+    pub queued_change : Pubkey,
+}
+
+#[event]
+pub struct PoolChangeCancelled{
+    pub pool : Pubkey,
+    pub queued_change : Pubkey,
+}
+
 #[error]
 pub enum PoolError {
     #[msg("Token mint to failed")]
@@ -936,4 +3792,175 @@ pub enum PoolError {
 
     #[msg("Invalid seller")]
     InvalidSeller,
+
+    #[msg("Invalid pending owner")]
+    InvalidPendingOwner,
+
+    #[msg("Invalid threshold")]
+    InvalidThreshold,
+
+    #[msg("Invalid authority signer")]
+    InvalidAuthoritySigner,
+
+    #[msg("Invalid proposal")]
+    InvalidProposal,
+
+    #[msg("Proposal is stale")]
+    StaleProposal,
+
+    #[msg("Proposal already executed")]
+    ProposalAlreadyExecuted,
+
+    #[msg("Not enough approvals")]
+    NotEnoughApprovals,
+
+    #[msg("Missing pool role")]
+    MissingPoolRole,
+
+    #[msg("Invalid upgrade authority")]
+    InvalidUpgradeAuthority,
+
+    #[msg("Paused")]
+    Paused,
+
+    #[msg("Account already migrated")]
+    AlreadyMigrated,
+
+    #[msg("Too many payment mints")]
+    TooManyPaymentMints,
+
+    #[msg("Invalid fee")]
+    InvalidFee,
+
+    #[msg("Invalid fee treasury")]
+    InvalidFeeTreasury,
+
+    #[msg("Change must go through the timelock")]
+    TimelockActive,
+
+    #[msg("Invalid timelock delay")]
+    InvalidTimelockDelay,
+
+    #[msg("Timelock has not expired")]
+    TimelockNotExpired,
+
+    #[msg("Invalid change target")]
+    InvalidChangeTarget,
+
+    #[msg("Invalid merkle proof")]
+    InvalidMerkleProof,
+
+    #[msg("Invalid mint record")]
+    InvalidMintRecord,
+
+    #[msg("Invalid mint phase")]
+    InvalidMintPhase,
+
+    #[msg("Mint phase is closed")]
+    MintPhaseClosed,
+
+    #[msg("Public mint is disabled")]
+    PublicMintDisabled,
+
+    #[msg("Wallet mint limit reached")]
+    WalletLimitReached,
+
+    #[msg("Invalid treasury")]
+    InvalidTreasury,
+
+    #[msg("Sold out")]
+    SoldOut,
+
+    #[msg("Invalid max supply")]
+    InvalidMaxSupply,
+
+    #[msg("Whitelist entries do not match the client accounts")]
+    InvalidWhitelistBatch,
+
+    #[msg("Invalid client account")]
+    InvalidClientAccount,
+
+    #[msg("Invalid config lines")]
+    InvalidConfigLines,
+
+    #[msg("Invalid creators")]
+    InvalidCreators,
+
+    #[msg("Invalid metadata account")]
+    InvalidMetadata,
+
+    #[msg("Invalid collection")]
+    InvalidCollection,
+
+    #[msg("Invalid edition")]
+    InvalidEdition,
+
+    #[msg("Mint batch accounts do not match the bumps")]
+    InvalidMintBatch,
+
+    #[msg("Invalid hidden metadata")]
+    InvalidHiddenMetadata,
+
+    #[msg("Reveal hash already committed")]
+    RevealCommitted,
+
+    #[msg("Already revealed")]
+    AlreadyRevealed,
+
+    #[msg("Invalid tier")]
+    InvalidTier,
+
+    #[msg("Tier is not open")]
+    TierClosed,
+
+    #[msg("Account must be migrated first")]
+    AccountNotMigrated,
+
+    #[msg("Account data does not match its version")]
+    InvalidAccountLayout,
+
+    #[msg("Pool still has open role or config lines accounts")]
+    OpenPoolAccounts,
+
+    #[msg("Treasury is not empty")]
+    TreasuryNotEmpty,
+
+    #[msg("Invalid pool authority")]
+    InvalidPoolAuthority,
+
+    #[msg("Metadata is still in use")]
+    MetadataInUse,
+
+    #[msg("Invalid sale manager")]
+    InvalidSaleManager,
+
+    #[msg("Invalid recipient")]
+    InvalidRecipient,
+
+    #[msg("Escrow is not empty")]
+    EscrowNotEmpty,
+
+    #[msg("NFT was not minted by this pool")]
+    NotPoolNft,
+
+    #[msg("Edition mode cannot be combined with a collection or hidden mode")]
+    EditionModeConflict,
+
+    #[msg("Invalid roles")]
+    InvalidRoles,
+
+    #[msg("Invalid sale pot")]
+    InvalidSalePot,
+
+    #[msg("Pool has active listings")]
+    ActiveListings,
+
+    #[msg("Invalid close authority")]
+    InvalidCloseAuthority,
+
+    #[msg("Invalid pool registry")]
+    InvalidPoolRegistry,
+
+    #[msg("Pool registry has active pools")]
+    ActivePools,
 }
\ No newline at end of file
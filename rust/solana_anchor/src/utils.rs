@@ -1,13 +1,1093 @@
 use {
-    crate::PoolError,
+    crate::{PoolError,Pool,MintTier,MAX_MINT_TIERS,PoolRole,ProgramConfig,SalePot,SaleManager,LegacySaleManager,LegacySalePot,LEGACY_SALE_MANAGER_SIZE,LEGACY_SALE_POT_SIZE,POOL_VERSION,SALE_MANAGER_VERSION,SALE_POT_VERSION,Metadata,MintRecord,MINT_RECORD_SIZE,Client,WhitelistEntry,CLIENT_VERSION,CLIENT_SIZE,CLIENT_PADDING,
+        MetadataExtended,METADATA_EXTENDED_VERSION,METADATA_EXTENDED_SIZE,METADATA_EXTENDED_PADDING,
+        ConfigLines,ConfigLine,Creator,MAX_METADATA_CREATORS,CONFIG_LINES_SIZE,CONFIG_LINE_SIZE,MAX_NAME_LENGTH,MAX_URI_LENGTH},
     anchor_lang::{
-        prelude::{AccountInfo, ProgramResult,},
+        AccountDeserialize,
+        AccountSerialize,
+        AnchorDeserialize,
+        Discriminator,
+        AnchorSerialize,
+        Key,
+        prelude::{AccountInfo, Clock, ProgramAccount, ProgramError, ProgramResult, Pubkey, Rent, Sysvar,},
         solana_program::{
             program::{invoke_signed, invoke},
+            program_pack::Pack,
+            instruction::{Instruction, AccountMeta},
+            bpf_loader_upgradeable,
+            system_program,
+            sysvar,
+            system_instruction,
+            hash::hashv,
         },
     },
+    metaplex_token_metadata::{
+        instruction::{create_metadata_accounts,create_master_edition,sign_metadata,mint_new_edition_from_master_edition_via_token,update_metadata_accounts,update_primary_sale_happened_via_token},
+        state::MasterEditionV2,
+    },
+    spl_token::state,
 };
 
+pub fn assert_authority_signers(signers : &[Pubkey], threshold : u8) -> ProgramResult {
+    if signers.len() > crate::MAX_AUTHORITY_SIGNERS {
+        return Err(PoolError::InvalidAuthoritySigner.into());
+    }
+    for i in 0..signers.len() {
+        if signers[i+1..].contains(&signers[i]) {
+            return Err(PoolError::InvalidAuthoritySigner.into());
+        }
+    }
+    if threshold == 0 || threshold as usize > signers.len() {
+        return Err(PoolError::InvalidThreshold.into());
+    }
+    Ok(())
+}
+
+pub fn assert_pool_role(
+    program_id : &Pubkey,
+    pool : &ProgramAccount<Pool>,
+    authority : &Pubkey,
+    pool_role_info : &AccountInfo,
+    role : u8,
+    ) -> ProgramResult {
+    assert_pool_version(pool)?;
+    if pool.owner == *authority {
+        return Ok(());
+    }
+    if pool_role_info.owner != program_id {
+        return Err(PoolError::MissingPoolRole.into());
+    }
+    let pool_role : PoolRole = PoolRole::try_deserialize(&mut &pool_role_info.data.borrow()[..])?;
+    if pool_role.pool != pool.key() || pool_role.member != *authority || pool_role.roles & role == 0 {
+        return Err(PoolError::MissingPoolRole.into());
+    }
+    Ok(())
+}
+
+pub fn assert_not_paused(pool : &ProgramAccount<Pool>, config : &ProgramAccount<ProgramConfig>) -> ProgramResult {
+    assert_pool_version(pool)?;
+    if pool.paused || config.paused {
+        return Err(PoolError::Paused.into());
+    }
+    Ok(())
+}
+
+// UpgradeableLoaderState is bincode encoded: a u32 variant tag, then
+// Program { programdata_address } or ProgramData { slot, upgrade_authority_address }.
+pub fn assert_upgrade_authority(
+    program : &AccountInfo,
+    program_data : &AccountInfo,
+    authority : &Pubkey,
+    ) -> ProgramResult {
+    if *program.owner != bpf_loader_upgradeable::id() || *program_data.owner != bpf_loader_upgradeable::id() {
+        return Err(PoolError::InvalidUpgradeAuthority.into());
+    }
+    let program_state = program.data.borrow();
+    if program_state.len() < 36 || program_state[0..4] != 2u32.to_le_bytes() || program_state[4..36] != program_data.key.to_bytes() {
+        return Err(PoolError::InvalidUpgradeAuthority.into());
+    }
+    let program_data_state = program_data.data.borrow();
+    if program_data_state.len() < 45 || program_data_state[0..4] != 3u32.to_le_bytes() || program_data_state[12] != 1 || program_data_state[13..45] != authority.to_bytes() {
+        return Err(PoolError::InvalidUpgradeAuthority.into());
+    }
+    Ok(())
+}
+
+pub fn assert_payment_mint(pool : &Pool, mint : &Pubkey) -> ProgramResult {
+    if pool.sale_mint != *mint && !pool.payment_mints.contains(mint) {
+        return Err(PoolError::InvalidMintAccount.into());
+    }
+    Ok(())
+}
+
+// Sale pots listed before payment mints were recorded priced in the pool's sale_mint.
+pub fn sale_pot_payment_mint(pool : &Pool, sale_pot : &SalePot) -> Pubkey {
+    if sale_pot.payment_mint == Pubkey::default() {
+        pool.sale_mint
+    } else {
+        sale_pot.payment_mint
+    }
+}
+
+// Pools without a schedule fall back to the manual presale_live switch. With a
+// schedule, whitelisted mints are open in any active phase and public mints
+// only in a public one.
+// A max_supply of zero leaves the pool uncapped.
+pub fn assert_mint_open(pool : &Pool, now : i64, public : bool) -> ProgramResult {
+    if pool.max_supply != 0 && pool.minted >= pool.max_supply {
+        return Err(PoolError::SoldOut.into());
+    }
+    if pool.phases.is_empty() {
+        if !pool.presale_live {
+            return Err(PoolError::PresaleNotLive.into());
+        }
+        return Ok(());
+    }
+    let phase = pool.phases.iter().find(|p| p.start_ts <= now && now < p.end_ts)
+        .ok_or(PoolError::MintPhaseClosed)?;
+    if public && phase.kind != crate::PHASE_PUBLIC {
+        return Err(PoolError::MintPhaseClosed.into());
+    }
+    Ok(())
+}
+
+// phase_prices is indexed like phases; a non-zero entry overrides the pool-wide price.
+pub fn current_mint_price(pool : &Pool, now : i64) -> u64 {
+    pool.phases.iter()
+        .position(|p| p.start_ts <= now && now < p.end_ts)
+        .and_then(|i| pool.phase_prices.get(i).copied())
+        .filter(|price| *price != 0)
+        .unwrap_or(pool.mint_price)
+}
+
+// Tier 0 leaves a wallet untiered; tier n refers to pool.tiers[n-1].
+pub fn validate_client_tier(pool : &Pool, tier : u8) -> ProgramResult {
+    if tier as usize > pool.tiers.len() {
+        return Err(PoolError::InvalidTier.into());
+    }
+    Ok(())
+}
+
+// Returns the tier price, if the wallet belongs to a tier, after checking the
+// tier window and that `count` more mints stay within its wallet cap.
+pub fn assert_tier(pool : &Pool, tier : u8, minted : u64, now : i64, count : u64) -> Result<Option<u64>, ProgramError> {
+    if tier == 0 {
+        return Ok(None);
+    }
+    let tier = pool.tiers.get(tier as usize - 1).ok_or(PoolError::InvalidTier)?;
+    if tier.end_ts != 0 && (now < tier.start_ts || now >= tier.end_ts) {
+        return Err(PoolError::TierClosed.into());
+    }
+    if tier.wallet_cap != 0 && minted + count > tier.wallet_cap {
+        return Err(PoolError::WalletLimitReached.into());
+    }
+    Ok(Some(tier.price))
+}
+
+pub fn assert_client_tier(pool : &Pool, client : &Client, now : i64, count : u64) -> Result<Option<u64>, ProgramError> {
+    assert_tier(pool, client.tier, client.minted, now, count)
+}
+
+pub struct CollectMintPaymentParams<'a, 'b> {
+    pub program_id : &'b Pubkey,
+    pub pool : &'b mut ProgramAccount<'a, Pool>,
+    pub payer : AccountInfo<'a>,
+    pub payer_token : AccountInfo<'a>,
+    pub treasury : AccountInfo<'a>,
+    pub treasury_token : AccountInfo<'a>,
+    pub token_program : AccountInfo<'a>,
+    pub system_program : AccountInfo<'a>,
+    pub now : i64,
+    // Overrides the pool and phase price, e.g. for a whitelist tier.
+    pub price : Option<u64>,
+}
+
+// Moves the mint price from the minter into the pool treasury PDA, in lamports
+// or in the pool's sale_mint depending on the pool setting.
+pub fn collect_mint_payment(params : CollectMintPaymentParams<'_, '_>) -> ProgramResult {
+    let CollectMintPaymentParams {
+        program_id,
+        pool,
+        payer,
+        payer_token,
+        treasury,
+        treasury_token,
+        token_program,
+        system_program,
+        now,
+        price,
+    } = params;
+
+    let price = price.unwrap_or_else(|| current_mint_price(pool, now));
+    if price == 0 {
+        return Ok(());
+    }
+    let pool_key = pool.key();
+    let treasury_key = Pubkey::create_program_address(&[b"treasury".as_ref(), pool_key.as_ref(), &[pool.treasury_bump]], program_id)
+        .map_err(|_| PoolError::InvalidTreasury)?;
+    if *treasury.key != treasury_key {
+        return Err(PoolError::InvalidTreasury.into());
+    }
+
+    if pool.mint_price_native {
+        invoke(
+            &system_instruction::transfer(payer.key, treasury.key, price),
+            &[payer, treasury, system_program],
+        )?;
+    } else {
+        let payer_token_data : state::Account = state::Account::unpack_from_slice(&payer_token.data.borrow())?;
+        let treasury_token_data : state::Account = state::Account::unpack_from_slice(&treasury_token.data.borrow())?;
+        if payer_token_data.mint != pool.sale_mint || payer_token_data.owner != *payer.key {
+            return Err(PoolError::InvalidTokenAccount.into());
+        }
+        if treasury_token_data.mint != pool.sale_mint || treasury_token_data.owner != treasury_key {
+            return Err(PoolError::InvalidTokenAccount.into());
+        }
+        if payer_token_data.amount < price {
+            return Err(PoolError::NotEnoughTokenAmount.into());
+        }
+        spl_token_transfer_without_seed(
+            TokenTransferParamsWithoutSeed{
+                source : payer_token,
+                destination : treasury_token,
+                amount : price,
+                authority : payer,
+                token_program : token_program,
+            }
+        )?;
+        pool.treasury_balance = pool.treasury_balance + price;
+    }
+    Ok(())
+}
+
+pub fn validate_mint_tiers(tiers : &[MintTier]) -> ProgramResult {
+    if tiers.len() > MAX_MINT_TIERS {
+        return Err(PoolError::InvalidTier.into());
+    }
+    for tier in tiers.iter() {
+        if tier.start_ts > tier.end_ts || (tier.start_ts == tier.end_ts && tier.start_ts != 0) {
+            return Err(PoolError::InvalidTier.into());
+        }
+    }
+    Ok(())
+}
+
+// Pairs are hashed in sorted order, so proofs carry no left/right flags.
+pub fn verify_merkle_proof(proof : &[[u8; 32]], root : &[u8; 32], leaf : [u8; 32]) -> bool {
+    let mut computed = leaf;
+    for node in proof {
+        computed = if computed <= *node {
+            hashv(&[computed.as_ref(), node.as_ref()]).to_bytes()
+        } else {
+            hashv(&[node.as_ref(), computed.as_ref()]).to_bytes()
+        };
+    }
+    computed == *root
+}
+
+pub fn write_account<T : AccountSerialize>(account : &T, info : &AccountInfo) -> ProgramResult {
+    let mut data = info.try_borrow_mut_data()?;
+    let dst : &mut [u8] = &mut data;
+    let mut cursor = std::io::Cursor::new(dst);
+    account.try_serialize(&mut cursor)
+}
+
+// Drains an account the program owns into `recipient` and clears its data, as
+// the close constraint does for accounts that are not in the context.
+pub fn close_account(info : &AccountInfo, recipient : &AccountInfo) -> ProgramResult {
+    let lamports = info.lamports();
+    **recipient.lamports.borrow_mut() = recipient.lamports().checked_add(lamports).ok_or(PoolError::InvalidAmount)?;
+    **info.lamports.borrow_mut() = 0;
+    let mut data = info.try_borrow_mut_data()?;
+    for byte in data.iter_mut() {
+        *byte = 0;
+    }
+    Ok(())
+}
+
+// Reads an account written before versioning, which carries the discriminator
+// of its current type `T` and was allocated at exactly `8+size`.
+pub fn load_legacy_account<T : Discriminator, L : AnchorDeserialize>(program_id : &Pubkey, info : &AccountInfo, size : usize) -> Result<L, ProgramError> {
+    if info.owner != program_id {
+        return Err(PoolError::InvalidAccountLayout.into());
+    }
+    let data = info.data.borrow();
+    if data.len() < 8 || data[..8] != T::discriminator() {
+        return Err(PoolError::InvalidAccountLayout.into());
+    }
+    if data.len() != 8+size {
+        return Err(PoolError::AlreadyMigrated.into());
+    }
+    L::deserialize(&mut &data[8..]).map_err(|_| PoolError::InvalidAccountLayout.into())
+}
+
+fn write_legacy_account<L : AnchorSerialize>(account : &L, info : &AccountInfo) -> ProgramResult {
+    let bytes = account.try_to_vec()?;
+    let mut data = info.try_borrow_mut_data()?;
+    if data.len() < 8+bytes.len() {
+        return Err(PoolError::InvalidAccountLayout.into());
+    }
+    data[8..8+bytes.len()].copy_from_slice(&bytes);
+    Ok(())
+}
+
+// Every pool instruction needs the current layout.
+pub fn assert_pool_version(pool : &Pool) -> ProgramResult {
+    if pool.version != POOL_VERSION {
+        return Err(PoolError::AccountNotMigrated.into());
+    }
+    Ok(())
+}
+
+// Loads a sale manager in either layout; the flag is set for a listing made
+// before versioning, which is keyed on the migrated pool's legacy_pool.
+pub fn load_sale_manager(program_id : &Pubkey, info : &AccountInfo) -> Result<(SaleManager, bool), ProgramError> {
+    if info.owner != program_id {
+        return Err(PoolError::InvalidSaleManager.into());
+    }
+    if info.data_len() != 8+LEGACY_SALE_MANAGER_SIZE {
+        let sale_manager : SaleManager = SaleManager::try_deserialize(&mut &info.data.borrow()[..])?;
+        return Ok((sale_manager, false));
+    }
+    let legacy : LegacySaleManager = load_legacy_account::<SaleManager, LegacySaleManager>(program_id, info, LEGACY_SALE_MANAGER_SIZE)?;
+    Ok((SaleManager{
+        version : SALE_MANAGER_VERSION,
+        pool : legacy.pool,
+        seller : legacy.seller,
+        nft_mint : legacy.nft_mint,
+        nft_pot : legacy.nft_pot,
+        sale_pot : legacy.sale_pot,
+        price : legacy.price,
+        sale_state : legacy.sale_state,
+        is_primary : legacy.is_primary,
+        bump : legacy.bump,
+    }, true))
+}
+
+pub fn store_sale_manager(sale_manager : &SaleManager, legacy : bool, info : &AccountInfo) -> ProgramResult {
+    if !legacy {
+        return write_account(sale_manager, info);
+    }
+    write_legacy_account(&LegacySaleManager{
+        pool : sale_manager.pool,
+        seller : sale_manager.seller,
+        nft_mint : sale_manager.nft_mint,
+        nft_pot : sale_manager.nft_pot,
+        sale_pot : sale_manager.sale_pot,
+        price : sale_manager.price,
+        sale_state : sale_manager.sale_state,
+        is_primary : sale_manager.is_primary,
+        bump : sale_manager.bump,
+    }, info)
+}
+
+// Checks that a sale manager sits at its PDA under `pool`, or under the pool
+// it was migrated from for a legacy listing.
+pub fn assert_sale_manager(program_id : &Pubkey, pool : &ProgramAccount<Pool>, sale_manager : &SaleManager, legacy : bool, info : &AccountInfo) -> ProgramResult {
+    let listing_pool = if legacy { pool.legacy_pool } else { pool.key() };
+    if listing_pool == Pubkey::default() || sale_manager.pool != listing_pool {
+        return Err(PoolError::InvalidPoolAccount.into());
+    }
+    let expected = Pubkey::create_program_address(&[sale_manager.pool.as_ref(), sale_manager.nft_mint.as_ref(), &[sale_manager.bump]], program_id)
+        .map_err(|_| PoolError::InvalidSaleManager)?;
+    if expected != *info.key {
+        return Err(PoolError::InvalidSaleManager.into());
+    }
+    Ok(())
+}
+
+// Legacy pots predate the marketplace fee and escrow tracking, so a sold one
+// holds its whole price; the claimed flags keep any share from being paid out
+// twice.
+pub fn load_sale_pot(program_id : &Pubkey, info : &AccountInfo) -> Result<(SalePot, bool), ProgramError> {
+    if info.owner != program_id {
+        return Err(PoolError::InvalidSalePot.into());
+    }
+    if info.data_len() != 8+LEGACY_SALE_POT_SIZE {
+        let sale_pot : SalePot = SalePot::try_deserialize(&mut &info.data.borrow()[..])?;
+        return Ok((sale_pot, false));
+    }
+    let legacy : LegacySalePot = load_legacy_account::<SalePot, LegacySalePot>(program_id, info, LEGACY_SALE_POT_SIZE)?;
+    Ok((SalePot{
+        version : SALE_POT_VERSION,
+        is_used : legacy.is_used,
+        sale_manager : legacy.sale_manager,
+        pool_pot : legacy.pool_pot,
+        price : legacy.price,
+        is_primary : legacy.is_primary,
+        seller : legacy.seller,
+        seller_verified : legacy.seller_verified,
+        seller_fee_basis_points : legacy.seller_fee_basis_points,
+        creators : legacy.creators,
+        is_native : false,
+        payment_mint : Pubkey::default(),
+        marketplace_fee_basis_points : 0,
+        escrow : if legacy.is_used { legacy.price } else { 0 },
+    }, true))
+}
+
+pub fn store_sale_pot(sale_pot : &SalePot, legacy : bool, info : &AccountInfo) -> ProgramResult {
+    if !legacy {
+        return write_account(sale_pot, info);
+    }
+    write_legacy_account(&LegacySalePot{
+        is_used : sale_pot.is_used,
+        sale_manager : sale_pot.sale_manager,
+        pool_pot : sale_pot.pool_pot,
+        price : sale_pot.price,
+        is_primary : sale_pot.is_primary,
+        seller : sale_pot.seller,
+        seller_verified : sale_pot.seller_verified,
+        seller_fee_basis_points : sale_pot.seller_fee_basis_points,
+        creators : sale_pot.creators.clone(),
+    }, info)
+}
+
+pub struct ClientParams<'a, 'b> {
+    pub program_id : &'b Pubkey,
+    pub pool : Pubkey,
+    pub payer : AccountInfo<'a>,
+    pub client : AccountInfo<'a>,
+    pub entry : &'b WhitelistEntry,
+    pub system_program : AccountInfo<'a>,
+    pub rent : AccountInfo<'a>,
+}
+
+// Same account set_whitelist creates through its init constraint, for callers
+// that pass clients in remaining_accounts.
+pub fn create_client(params : ClientParams<'_, '_>) -> ProgramResult {
+    let ClientParams {
+        program_id,
+        pool,
+        payer,
+        client,
+        entry,
+        system_program,
+        rent,
+    } = params;
+
+    let seeds = &[
+        program_id.as_ref(),
+        pool.as_ref(),
+        entry.bidder.as_ref(),
+        &[entry.bump]
+    ];
+    let expected = Pubkey::create_program_address(seeds, program_id)
+        .map_err(|_| PoolError::InvalidClientAccount)?;
+    if *client.key != expected || !client.data_is_empty() {
+        return Err(PoolError::InvalidClientAccount.into());
+    }
+
+    let rent = Rent::from_account_info(&rent)?;
+    let space = 8+CLIENT_SIZE+CLIENT_PADDING;
+    create_pda_account(program_id, &payer, &client, space, &rent, seeds, &system_program)?;
+    write_account(&Client{
+        version : CLIENT_VERSION,
+        owner : entry.bidder,
+        pool : pool,
+        amount : entry.amount,
+        whitelisted : entry.whitelisted,
+        bump : entry.bump,
+        tier : entry.tier,
+        minted : 0,
+    }, &client)
+}
+
+pub fn update_client(program_id : &Pubkey, pool : &Pubkey, client : &AccountInfo, entry : &WhitelistEntry) -> ProgramResult {
+    if client.owner != program_id {
+        return Err(PoolError::InvalidClientAccount.into());
+    }
+    let mut data : Client = Client::try_deserialize(&mut &client.data.borrow()[..])?;
+    if data.pool != *pool || data.owner != entry.bidder {
+        return Err(PoolError::InvalidClientAccount.into());
+    }
+    data.amount = entry.amount;
+    data.whitelisted = entry.whitelisted;
+    data.tier = entry.tier;
+    write_account(&data, client)
+}
+
+fn config_line_offset(index : usize) -> usize {
+    8+CONFIG_LINES_SIZE+CONFIG_LINE_SIZE*index
+}
+
+pub fn write_config_line(info : &AccountInfo, index : usize, line : &ConfigLine) -> ProgramResult {
+    if line.name.len() > MAX_NAME_LENGTH || line.uri.len() > MAX_URI_LENGTH {
+        return Err(PoolError::InvalidConfigLines.into());
+    }
+    let bytes = line.try_to_vec()?;
+    let offset = config_line_offset(index);
+    let mut data = info.try_borrow_mut_data()?;
+    data[offset..offset+bytes.len()].copy_from_slice(&bytes);
+    Ok(())
+}
+
+// Lines before `redeemed` are used up. In random mode the chosen line is swapped
+// into the `redeemed` slot first, so every line is minted exactly once. The pick
+// mixes the slot, the timestamp and the new mint key. A minter can grind mint
+// keys to steer it, so it only keeps the mint order from being sequential.
+pub fn take_config_line(config : &mut ConfigLines, info : &AccountInfo, clock : &Clock, mint : &Pubkey) -> Result<ConfigLine, ProgramError> {
+    if config.redeemed >= config.loaded {
+        return Err(PoolError::SoldOut.into());
+    }
+    let next = config.redeemed as usize;
+    let mut pick = next;
+    if config.random {
+        let hash = hashv(&[&clock.slot.to_le_bytes(), &clock.unix_timestamp.to_le_bytes(), mint.as_ref()]);
+        let mut seed = [0u8; 8];
+        seed.copy_from_slice(&hash.to_bytes()[..8]);
+        pick = next + (u64::from_le_bytes(seed) % (config.loaded - config.redeemed) as u64) as usize;
+    }
+
+    let mut data = info.try_borrow_mut_data()?;
+    let next_offset = config_line_offset(next);
+    if pick != next {
+        let (head, tail) = data.split_at_mut(config_line_offset(pick));
+        head[next_offset..next_offset+CONFIG_LINE_SIZE].swap_with_slice(&mut tail[..CONFIG_LINE_SIZE]);
+    }
+    let line = ConfigLine::deserialize(&mut &data[next_offset..next_offset+CONFIG_LINE_SIZE])?;
+    config.redeemed = config.redeemed + 1;
+    Ok(line)
+}
+
+pub fn is_hidden(pool : &Pool) -> bool {
+    pool.reveal_root != [0; 32] && pool.edition_mint == Pubkey::default()
+}
+
+// Metadata for the next mint: None in edition mode, the pool placeholder in
+// hidden mode (config lines only supply symbol, royalty and creators), and the
+// next config line otherwise.
+pub fn next_mint_metadata(program_id : &Pubkey, pool : &ProgramAccount<Pool>, config_lines : &AccountInfo, clock : &Clock, mint : &Pubkey) -> Result<Option<Metadata>, ProgramError> {
+    if pool.edition_mint != Pubkey::default() {
+        return Ok(None);
+    }
+    if *config_lines.key != pool.config_lines {
+        return Err(PoolError::InvalidConfigLines.into());
+    }
+    if is_hidden(pool) {
+        if config_lines.owner != program_id {
+            return Err(PoolError::InvalidConfigLines.into());
+        }
+        let config : ConfigLines = ConfigLines::try_deserialize(&mut &config_lines.data.borrow()[..])?;
+        if config.pool != pool.key() || !config.is_mutable {
+            return Err(PoolError::InvalidConfigLines.into());
+        }
+        return Ok(Some(config_line_metadata(&config, ConfigLine{
+            name : pool.hidden_name.clone(),
+            uri : pool.hidden_uri.clone(),
+        })));
+    }
+    Ok(Some(load_config_line_metadata(program_id, &pool.key(), config_lines, clock, mint)?))
+}
+
+pub fn load_config_line_metadata(program_id : &Pubkey, pool : &Pubkey, info : &AccountInfo, clock : &Clock, mint : &Pubkey) -> Result<Metadata, ProgramError> {
+    if info.owner != program_id {
+        return Err(PoolError::InvalidConfigLines.into());
+    }
+    let mut config : ConfigLines = ConfigLines::try_deserialize(&mut &info.data.borrow()[..])?;
+    if config.pool != *pool {
+        return Err(PoolError::InvalidConfigLines.into());
+    }
+    let line = take_config_line(&mut config, info, clock, mint)?;
+    write_account(&config, info)?;
+    Ok(config_line_metadata(&config, line))
+}
+
+pub fn config_line_metadata(config : &ConfigLines, line : ConfigLine) -> Metadata {
+    Metadata{
+        name : line.name,
+        symbol : config.symbol.clone(),
+        uri : line.uri,
+        seller_fee_basis_points : config.seller_fee_basis_points,
+        creators : config.creators.clone(),
+        is_mutable : config.is_mutable,
+    }
+}
+
+pub fn pool_signer_bump(program_id : &Pubkey, pool : &Pubkey) -> u8 {
+    Pubkey::find_program_address(&[b"pool_signer".as_ref(), pool.as_ref()], program_id).1
+}
+
+pub fn treasury_bump(program_id : &Pubkey, pool : &Pubkey) -> u8 {
+    Pubkey::find_program_address(&[b"treasury".as_ref(), pool.as_ref()], program_id).1
+}
+
+// Every pool NFT lists the pool signer PDA as its first, verified creator with a
+// zero share, so the metadata alone proves which pool minted it.
+pub fn is_pool_nft(metadata : &metaplex_token_metadata::state::Metadata, pool_signer : &Pubkey) -> bool {
+    match &metadata.data.creators {
+        Some(creators) => creators.first().map_or(false, |c| c.address == *pool_signer && c.verified),
+        None => false,
+    }
+}
+
+pub fn load_nft_metadata(info : &AccountInfo, mint : &Pubkey) -> Result<metaplex_token_metadata::state::Metadata, ProgramError> {
+    if *info.owner != metaplex_token_metadata::id() {
+        return Err(PoolError::InvalidMetadata.into());
+    }
+    let metadata = metaplex_token_metadata::state::Metadata::from_account_info(info)?;
+    if metadata.mint != *mint {
+        return Err(PoolError::InvalidMetadata.into());
+    }
+    Ok(metadata)
+}
+
+pub fn validate_creators(creators : &[Creator], pool_signer : &Pubkey) -> ProgramResult {
+    // The first metadata creator slot is taken by the pool signer, so it counts
+    // toward the metadata limit and cannot be listed again.
+    if creators.is_empty() || creators.len()+1 > MAX_METADATA_CREATORS {
+        return Err(PoolError::InvalidCreators.into());
+    }
+    let mut total : u16 = 0;
+    for (i, c) in creators.iter().enumerate() {
+        if c.address == *pool_signer || creators[..i].iter().any(|other| other.address == c.address) {
+            return Err(PoolError::InvalidCreators.into());
+        }
+        total = total + c.share as u16;
+    }
+    if total != 100 {
+        return Err(PoolError::InvalidCreators.into());
+    }
+    Ok(())
+}
+
+pub struct MetadataExtendedParams<'a, 'b> {
+    pub program_id : &'b Pubkey,
+    pub pool : Pubkey,
+    pub mint : &'b Pubkey,
+    pub payer : AccountInfo<'a>,
+    pub metadata_extended : AccountInfo<'a>,
+    pub bump : u8,
+    pub mint_index : u64,
+    pub hidden : bool,
+    pub system_program : AccountInfo<'a>,
+    pub rent : AccountInfo<'a>,
+}
+
+// Same account mint_nft creates through its init constraint, for mints whose
+// accounts arrive in remaining_accounts.
+pub fn create_metadata_extended(params : MetadataExtendedParams<'_, '_>) -> ProgramResult {
+    let MetadataExtendedParams {
+        program_id,
+        pool,
+        mint,
+        payer,
+        metadata_extended,
+        bump,
+        mint_index,
+        hidden,
+        system_program,
+        rent,
+    } = params;
+
+    let seeds = &[
+        mint.as_ref(),
+        pool.as_ref(),
+        program_id.as_ref(),
+        &[bump]
+    ];
+    let expected = Pubkey::create_program_address(seeds, program_id)
+        .map_err(|_| PoolError::InvalidMintBatch)?;
+    if *metadata_extended.key != expected || !metadata_extended.data_is_empty() {
+        return Err(PoolError::InvalidMintBatch.into());
+    }
+
+    let minter = *payer.key;
+    let rent = Rent::from_account_info(&rent)?;
+    let space = 8+METADATA_EXTENDED_SIZE+METADATA_EXTENDED_PADDING;
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            metadata_extended.key,
+            rent.minimum_balance(space),
+            space as u64,
+            program_id,
+        ),
+        &[payer, metadata_extended.clone(), system_program],
+        &[seeds],
+    )?;
+    write_account(&MetadataExtended{
+        version : METADATA_EXTENDED_VERSION,
+        pool : pool,
+        max_price : 0,
+        bump : bump,
+        mint_index : mint_index,
+        hidden : hidden,
+        minter : minter,
+        legacy : false,
+    }, &metadata_extended)
+}
+
+pub struct MintRecordParams<'a, 'b> {
+    pub program_id : &'b Pubkey,
+    pub seed : &'b [u8],
+    pub pool : Pubkey,
+    pub wallet : AccountInfo<'a>,
+    pub mint_record : AccountInfo<'a>,
+    pub bump : u8,
+    pub system_program : AccountInfo<'a>,
+    pub rent : AccountInfo<'a>,
+}
+
+// Creates a program-owned PDA. Tops up rather than funds it from scratch, so
+// lamports sent to the address beforehand cannot block the creation the way
+// they block system_instruction::create_account.
+pub fn create_pda_account<'a>(
+    program_id : &Pubkey,
+    payer : &AccountInfo<'a>,
+    account : &AccountInfo<'a>,
+    space : usize,
+    rent : &Rent,
+    seeds : &[&[u8]],
+    system_program : &AccountInfo<'a>,
+    ) -> ProgramResult {
+    let required = rent.minimum_balance(space).saturating_sub(account.lamports());
+    if required > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, account.key, required),
+            &[payer.clone(), account.clone(), system_program.clone()],
+        )?;
+    }
+    invoke_signed(
+        &system_instruction::allocate(account.key, space as u64),
+        &[account.clone(), system_program.clone()],
+        &[seeds],
+    )?;
+    invoke_signed(
+        &system_instruction::assign(account.key, program_id),
+        &[account.clone(), system_program.clone()],
+        &[seeds],
+    )
+}
+
+// Loads the wallet's mint record, creating the PDA on the wallet's first mint.
+// Only the canonical bump is accepted: every other bump derives a different
+// address and would give the wallet a fresh record, and a fresh allocation.
+// Each mint path passes its own seed, so a whitelist allocation and the public
+// wallet limit are counted separately.
+pub fn load_mint_record(params : MintRecordParams<'_, '_>) -> Result<MintRecord, ProgramError> {
+    let MintRecordParams {
+        program_id,
+        seed,
+        pool,
+        wallet,
+        mint_record,
+        bump,
+        system_program,
+        rent,
+    } = params;
+
+    let (expected, canonical_bump) = Pubkey::find_program_address(&[seed, pool.as_ref(), wallet.key.as_ref()], program_id);
+    if *mint_record.key != expected || bump != canonical_bump {
+        return Err(PoolError::InvalidMintRecord.into());
+    }
+    let seeds = &[
+        seed,
+        pool.as_ref(),
+        wallet.key.as_ref(),
+        &[bump]
+    ];
+
+    if mint_record.data_is_empty() {
+        let rent = Rent::from_account_info(&rent)?;
+        create_pda_account(program_id, &wallet, &mint_record, 8+MINT_RECORD_SIZE, &rent, seeds, &system_program)?;
+        return Ok(MintRecord{
+            pool : pool,
+            owner : *wallet.key,
+            minted : 0,
+            bump : bump,
+        });
+    }
+
+    if mint_record.owner != program_id {
+        return Err(PoolError::InvalidMintRecord.into());
+    }
+    let record : MintRecord = MintRecord::try_deserialize(&mut &mint_record.data.borrow()[..])?;
+    if record.pool != pool || record.owner != *wallet.key {
+        return Err(PoolError::InvalidMintRecord.into());
+    }
+    Ok(record)
+}
+
+pub struct ListNftParams<'a, 'b> {
+    pub program_id : &'b Pubkey,
+    pub pool : &'b Pool,
+    pub pool_key : Pubkey,
+    pub metadata_extended : &'b MetadataExtended,
+    pub sale_manager : &'b mut SaleManager,
+    pub sale_manager_key : Pubkey,
+    pub sale_pot : &'b mut SalePot,
+    pub sale_pot_key : Pubkey,
+    pub seller : AccountInfo<'a>,
+    pub nft_mint : &'b Pubkey,
+    pub nft_seller_token : AccountInfo<'a>,
+    pub nft_manager_token : AccountInfo<'a>,
+    pub metadata : AccountInfo<'a>,
+    pub token_metadata_program : AccountInfo<'a>,
+    pub token_program : AccountInfo<'a>,
+    pub price : u64,
+    // Token account and mint the proceeds are paid into, or None for a listing
+    // priced in lamports and escrowed by the sale manager itself.
+    pub payment : Option<(Pubkey, Pubkey)>,
+}
+
+// Shared by sell_nft and sell_nft_native: records the listing on the sale
+// manager and its sale pot, then moves the NFT into the sale manager's custody.
+pub fn list_nft(params : ListNftParams<'_, '_>) -> ProgramResult {
+    let ListNftParams {
+        program_id,
+        pool,
+        pool_key,
+        metadata_extended,
+        sale_manager,
+        sale_manager_key,
+        sale_pot,
+        sale_pot_key,
+        seller,
+        nft_mint,
+        nft_seller_token,
+        nft_manager_token,
+        metadata,
+        token_metadata_program,
+        token_program,
+        price,
+        payment,
+    } = params;
+
+    let nft_seller_token_data : state::Account = state::Account::unpack_from_slice(&nft_seller_token.data.borrow())?;
+    let nft_manager_token_data : state::Account = state::Account::unpack_from_slice(&nft_manager_token.data.borrow())?;
+    let metadata_data = load_nft_metadata(&metadata, nft_mint)?;
+    let pool_signer = Pubkey::create_program_address(&[b"pool_signer".as_ref(), pool_key.as_ref(), &[pool.signer_bump]], program_id)
+        .map_err(|_| PoolError::NotPoolNft)?;
+    if !metadata_extended.legacy && !is_pool_nft(&metadata_data, &pool_signer) {
+        return Err(PoolError::NotPoolNft.into());
+    }
+    if nft_seller_token_data.owner != *seller.key {
+        return Err(PoolError::InvalidTokenAccount.into());
+    }
+    if nft_seller_token_data.mint != *nft_mint {
+        return Err(PoolError::InvalidTokenAccount.into());
+    }
+    if nft_manager_token_data.mint != *nft_mint {
+        return Err(PoolError::InvalidTokenAccount.into());
+    }
+    if metadata_extended.max_price < price && metadata_extended.max_price!=0 {
+        return Err(PoolError::InvalidPrice.into());
+    }
+    if metadata_extended.pool != pool_key {
+        return Err(PoolError::InvalidPoolAccount.into());
+    }
+    if sale_manager.sale_state == 1 {
+        return Err(PoolError::AlreadyTrading.into());
+    }
+    sale_manager.sale_pot = sale_pot_key;
+    sale_manager.seller = *seller.key;
+    sale_manager.price = price;
+    sale_manager.sale_state = 1;
+    sale_manager.nft_pot = *nft_manager_token.key;
+
+    sale_pot.version = SALE_POT_VERSION;
+    sale_pot.sale_manager = sale_manager_key;
+    sale_pot.is_used = false;
+    sale_pot.price = price;
+    sale_pot.seller_fee_basis_points = metadata_data.data.seller_fee_basis_points;
+    sale_pot.is_primary = !metadata_data.primary_sale_happened;
+    sale_pot.seller_verified = sale_pot.is_primary;
+    sale_pot.seller = *seller.key;
+    sale_pot.marketplace_fee_basis_points = if sale_pot.is_primary { 0 } else { pool.fee_basis_points };
+    match payment {
+        Some((pool_pot, payment_mint)) => {
+            sale_pot.is_native = false;
+            sale_pot.pool_pot = pool_pot;
+            sale_pot.payment_mint = payment_mint;
+        }
+        None => {
+            sale_pot.is_native = true;
+            sale_pot.pool_pot = sale_manager_key;
+            sale_pot.payment_mint = Pubkey::default();
+        }
+    }
+    sale_pot.escrow = 0;
+    if let Some(creators) = metadata_data.data.creators {
+        for c in creators {
+            sale_pot.creators.push(Creator{
+                address : c.address,
+                verified : false,
+                share : c.share,
+            })
+        }
+    }
+
+    spl_token_transfer_without_seed(
+        TokenTransferParamsWithoutSeed{
+            source : nft_seller_token,
+            destination : nft_manager_token,
+            amount : 1,
+            authority : seller.clone(),
+            token_program : token_program,
+        }
+    )?;
+    invoke(
+        &update_metadata_accounts(
+            *token_metadata_program.key,
+            *metadata.key,
+            *seller.key,
+            Some(sale_manager_key),
+            None,
+            None,
+        ),
+        &[
+            token_metadata_program,
+            metadata,
+            seller,
+        ]
+    )?;
+    Ok(())
+}
+
+// Checks shared by buy_nft and buy_nft_native. Returns the marketplace fee
+// taken out of the price.
+pub fn assert_purchase(
+    sale_manager : &SaleManager,
+    sale_pot : &SalePot,
+    sale_pot_key : &Pubkey,
+    nft_mint : &Pubkey,
+    nft_manager_token : &AccountInfo,
+    nft_bidder_token : &AccountInfo,
+    buyer : &Pubkey,
+    native : bool,
+    ) -> Result<u64, ProgramError> {
+    let nft_manager_token_data : state::Account = state::Account::unpack_from_slice(&nft_manager_token.data.borrow())?;
+    let nft_bidder_token_data : state::Account = state::Account::unpack_from_slice(&nft_bidder_token.data.borrow())?;
+    if nft_manager_token_data.mint != *nft_mint {
+        return Err(PoolError::InvalidTokenAccount.into());
+    }
+    if nft_bidder_token_data.mint != *nft_mint {
+        return Err(PoolError::InvalidTokenAccount.into());
+    }
+    if sale_manager.sale_state != 1 {
+        return Err(PoolError::InvalidSaleState.into());
+    }
+    if sale_manager.sale_pot != *sale_pot_key || sale_pot.is_native != native {
+        return Err(PoolError::InvalidSalePot.into());
+    }
+    if sale_manager.nft_mint != *nft_mint {
+        return Err(PoolError::InvalidMintAccount.into());
+    }
+    if sale_manager.seller == *buyer {
+        return Err(PoolError::InvalidBidder.into());
+    }
+    Ok(marketplace_fee(sale_manager.price, sale_pot.marketplace_fee_basis_points))
+}
+
+pub struct DeliverNftParams<'a, 'b> {
+    pub sale_manager : &'b mut SaleManager,
+    pub sale_manager_info : AccountInfo<'a>,
+    pub sale_pot : &'b mut SalePot,
+    pub buyer : AccountInfo<'a>,
+    pub nft_manager_token : AccountInfo<'a>,
+    pub nft_bidder_token : AccountInfo<'a>,
+    pub metadata : AccountInfo<'a>,
+    pub token_metadata_program : AccountInfo<'a>,
+    pub token_program : AccountInfo<'a>,
+    // Proceeds the sale pot now holds for its seller and creators.
+    pub escrow : u64,
+}
+
+// Hands a paid-for NFT to its buyer and marks the sale pot as sold.
+pub fn deliver_sold_nft(params : DeliverNftParams<'_, '_>) -> ProgramResult {
+    let DeliverNftParams {
+        sale_manager,
+        sale_manager_info,
+        sale_pot,
+        buyer,
+        nft_manager_token,
+        nft_bidder_token,
+        metadata,
+        token_metadata_program,
+        token_program,
+        escrow,
+    } = params;
+
+    let sale_manager_key = *sale_manager_info.key;
+    let pool = sale_manager.pool;
+    let nft_mint = sale_manager.nft_mint;
+    let sale_manager_seeds = &[
+        pool.as_ref(),
+        nft_mint.as_ref(),
+        &[sale_manager.bump]
+    ];
+    invoke_signed(
+        &update_primary_sale_happened_via_token(
+            *token_metadata_program.key,
+            *metadata.key,
+            sale_manager_key,
+            *nft_manager_token.key,
+        ),
+        &[
+            token_metadata_program.clone(),
+            metadata.clone(),
+            sale_manager_info.clone(),
+            nft_manager_token.clone(),
+        ],
+        &[sale_manager_seeds]
+    )?;
+    spl_token_transfer(
+        TokenTransferParams{
+            source : nft_manager_token,
+            destination : nft_bidder_token,
+            amount : 1,
+            authority : sale_manager_info.clone(),
+            authority_signer_seeds : sale_manager_seeds,
+            token_program : token_program,
+        }
+    )?;
+    invoke_signed(
+        &update_metadata_accounts(
+            *token_metadata_program.key,
+            *metadata.key,
+            sale_manager_key,
+            Some(*buyer.key),
+            None,
+            None,
+        ),
+        &[
+            token_metadata_program,
+            metadata,
+            sale_manager_info,
+        ],
+        &[sale_manager_seeds]
+    )?;
+
+    sale_manager.sale_state = 2;
+    sale_pot.is_used = true;
+    sale_pot.escrow = escrow;
+    Ok(())
+}
+
+// Caps a withdrawal at what the sale pot still escrows and takes it out.
+pub fn take_sale_pot_escrow(sale_pot : &mut SalePot, amount : u64) -> u64 {
+    let amount = if amount > sale_pot.escrow { sale_pot.escrow } else { amount };
+    sale_pot.escrow = sale_pot.escrow - amount;
+    amount
+}
+
+pub fn marketplace_fee(price : u64, fee_basis_points : u16) -> u64 {
+    ((price as u128) * (fee_basis_points as u128) / 10000) as u64
+}
+
+// Marks the withdrawer's seller and creator shares of a sale pot as claimed
+// and returns the amount they are owed, net of the marketplace fee.
+pub fn claim_withdraw_amount(sale_pot : &mut SalePot, withdrawer : &Pubkey) -> u64 {
+    let price = sale_pot.price - marketplace_fee(sale_pot.price, sale_pot.marketplace_fee_basis_points);
+    let mut amount : u64 = 0;
+    if sale_pot.is_primary {
+        // sale_pot.seller_verified = true;
+        let mut share : u8 = 0;
+        for i in 0..sale_pot.creators.len(){
+            if sale_pot.creators[i].verified==false && sale_pot.creators[i].address==*withdrawer {
+                sale_pot.creators[i].verified=true;
+                share = sale_pot.creators[i].share
+            }
+        }
+        amount =((price as f64) * (share as f64) / (100.0 as f64)) as u64;
+    } else {
+        if sale_pot.seller_verified==false && sale_pot.seller == *withdrawer {
+            amount = ((price as f64) * ((10000-sale_pot.seller_fee_basis_points) as f64) / (10000 as f64)) as u64;
+            sale_pot.seller_verified = true;
+        }
+        let mut share : u8 = 0;
+        for i in 0..sale_pot.creators.len(){
+            if sale_pot.creators[i].verified==false && sale_pot.creators[i].address==*withdrawer {
+                sale_pot.creators[i].verified=true;
+                share = sale_pot.creators[i].share
+            }
+        }
+        amount = amount + ((price as f64) * (sale_pot.seller_fee_basis_points as f64) / (10000 as f64) * (share as f64) / (100.0 as f64)) as u64
+    }
+    amount
+}
+
 ///TokenTransferParams
 pub struct TokenTransferParams<'a: 'b, 'b> {
     /// source
@@ -149,4 +1229,380 @@ pub fn spl_token_mint_to(params : TokenMintToParams<'_>) -> ProgramResult {
         &[mint,account,owner,token_program],
     );
     result.map_err(|_| PoolError::TokenMintToFailed.into())
+}
+
+// metaplex-token-metadata 0.0.1 predates collections, so the two instructions
+// needed for them are built here by hand against the deployed program.
+const CREATE_METADATA_ACCOUNT_V2 : u8 = 16;
+const VERIFY_COLLECTION : u8 = 18;
+
+#[derive(AnchorSerialize)]
+pub struct CollectionV2 {
+    pub verified : bool,
+    pub key : Pubkey,
+}
+
+#[derive(AnchorSerialize)]
+pub struct UsesV2 {
+    pub use_method : u8,
+    pub remaining : u64,
+    pub total : u64,
+}
+
+#[derive(AnchorSerialize)]
+pub struct DataV2 {
+    pub name : String,
+    pub symbol : String,
+    pub uri : String,
+    pub seller_fee_basis_points : u16,
+    pub creators : Option<Vec<Creator>>,
+    pub collection : Option<CollectionV2>,
+    pub uses : Option<UsesV2>,
+}
+
+pub fn create_metadata_accounts_v2(
+    program_id : Pubkey,
+    metadata : Pubkey,
+    mint : Pubkey,
+    mint_authority : Pubkey,
+    payer : Pubkey,
+    update_authority : Pubkey,
+    data : DataV2,
+    is_mutable : bool,
+) -> Result<Instruction, ProgramError> {
+    let mut instruction_data = vec![CREATE_METADATA_ACCOUNT_V2];
+    instruction_data.extend(data.try_to_vec()?);
+    instruction_data.push(is_mutable as u8);
+    Ok(Instruction{
+        program_id,
+        accounts : vec![
+            AccountMeta::new(metadata, false),
+            AccountMeta::new_readonly(mint, false),
+            AccountMeta::new_readonly(mint_authority, true),
+            AccountMeta::new(payer, true),
+            AccountMeta::new_readonly(update_authority, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data : instruction_data,
+    })
+}
+
+pub fn verify_collection(
+    program_id : Pubkey,
+    metadata : Pubkey,
+    collection_authority : Pubkey,
+    payer : Pubkey,
+    collection_mint : Pubkey,
+    collection : Pubkey,
+    collection_master_edition : Pubkey,
+) -> Instruction {
+    Instruction{
+        program_id,
+        accounts : vec![
+            AccountMeta::new(metadata, false),
+            AccountMeta::new(collection_authority, true),
+            AccountMeta::new(payer, true),
+            AccountMeta::new_readonly(collection_mint, false),
+            AccountMeta::new_readonly(collection, false),
+            AccountMeta::new_readonly(collection_master_edition, false),
+        ],
+        data : vec![VERIFY_COLLECTION],
+    }
+}
+
+pub fn master_edition_supply(master_edition : &AccountInfo) -> Result<u64, ProgramError> {
+    if *master_edition.owner != metaplex_token_metadata::id() {
+        return Err(PoolError::InvalidEdition.into());
+    }
+    // from_account_info lets an uninitialized key byte through.
+    let edition = MasterEditionV2::from_account_info(master_edition)?;
+    if edition.key != metaplex_token_metadata::state::Key::MasterEditionV2 {
+        return Err(PoolError::InvalidEdition.into());
+    }
+    Ok(edition.supply)
+}
+
+pub struct MintNftParams<'a, 'b> {
+    pub pool : Pubkey,
+    pub pool_signer : AccountInfo<'a>,
+    pub signer_bump : u8,
+    // Hidden mints hand update authority to the pool signer until reveal.
+    pub hidden : bool,
+    pub collection : Pubkey,
+    pub collection_mint : AccountInfo<'a>,
+    pub collection_metadata : AccountInfo<'a>,
+    pub collection_master_edition : AccountInfo<'a>,
+    pub edition_mint : Pubkey,
+    // Edition mode only: master metadata, master edition, the pool signer's
+    // master token account and the edition marker PDA, in that order.
+    pub edition_accounts : &'b [AccountInfo<'a>],
+    pub owner : AccountInfo<'a>,
+    pub mint : AccountInfo<'a>,
+    pub token_account : AccountInfo<'a>,
+    pub metadata : AccountInfo<'a>,
+    pub master_edition : AccountInfo<'a>,
+    pub token_metadata_program : AccountInfo<'a>,
+    pub token_program : AccountInfo<'a>,
+    pub system_program : AccountInfo<'a>,
+    pub rent : AccountInfo<'a>,
+    pub data : Option<Metadata>,
+}
+
+pub fn mint_nft_with_metadata(params : MintNftParams<'_, '_>) -> ProgramResult {
+    let MintNftParams {
+        pool,
+        pool_signer,
+        signer_bump,
+        hidden,
+        collection,
+        collection_mint,
+        collection_metadata,
+        collection_master_edition,
+        edition_mint,
+        edition_accounts,
+        owner,
+        mint,
+        token_account,
+        metadata,
+        master_edition,
+        token_metadata_program,
+        token_program,
+        system_program,
+        rent,
+        data,
+    } = params;
+
+    let mint_data : state::Mint = state::Mint::unpack_from_slice(&mint.data.borrow())?;
+    let token_account_data : state::Account = state::Account::unpack_from_slice(&token_account.data.borrow())?;
+    if mint_data.decimals != 0 {
+        return Err(PoolError::InvalidMintAccount.into());
+    }
+    if mint_data.supply != 0 {
+        return Err(PoolError::InvalidMintAccount.into());
+    }
+    if token_account_data.mint != *mint.key {
+        return Err(PoolError::InvalidTokenAccount.into());
+    }
+
+    spl_token_mint_to(
+        TokenMintToParams{
+            mint : mint.clone(),
+            account : token_account.clone(),
+            owner : owner.clone(),
+            token_program : token_program.clone(),
+            amount : 1 as u64,
+        }
+    )?;
+
+    let data = match data {
+        Some(data) => data,
+        None => {
+            if edition_accounts.len() != 4 {
+                return Err(PoolError::InvalidEdition.into());
+            }
+            let master_metadata = &edition_accounts[0];
+            let master_edition_account = &edition_accounts[1];
+            let master_token = &edition_accounts[2];
+            let edition_marker = &edition_accounts[3];
+            let master_token_data : state::Account = state::Account::unpack_from_slice(&master_token.data.borrow())?;
+            if master_token_data.mint != edition_mint || master_token_data.owner != *pool_signer.key {
+                return Err(PoolError::InvalidEdition.into());
+            }
+            let edition = master_edition_supply(master_edition_account)? + 1;
+            invoke_signed(
+                &mint_new_edition_from_master_edition_via_token(
+                    *token_metadata_program.key,
+                    *metadata.key,
+                    *master_edition.key,
+                    *master_edition_account.key,
+                    *mint.key,
+                    *owner.key,
+                    *owner.key,
+                    *pool_signer.key,
+                    *master_token.key,
+                    *owner.key,
+                    *master_metadata.key,
+                    edition_mint,
+                    edition,
+                ),
+                &[
+                    metadata.clone(),
+                    master_edition.clone(),
+                    master_edition_account.clone(),
+                    mint.clone(),
+                    edition_marker.clone(),
+                    owner.clone(),
+                    pool_signer.clone(),
+                    master_token.clone(),
+                    master_metadata.clone(),
+                    token_metadata_program.clone(),
+                    token_program.clone(),
+                    system_program.clone(),
+                    rent.clone(),
+                ],
+                &[&[b"pool_signer".as_ref(), pool.as_ref(), &[signer_bump]]]
+            )?;
+            return Ok(());
+        }
+    };
+
+    let mut creators : Vec<metaplex_token_metadata::state::Creator> = 
+        vec![metaplex_token_metadata::state::Creator{
+            address: *pool_signer.key,
+            verified : false,
+            share : 0,
+        }];
+    validate_creators(&data.creators, pool_signer.key)?;
+    // Only the minter signs create_metadata_accounts, so no other creator can be verified here.
+    // The pool signer verifies itself with sign_metadata right after.
+    for c in data.creators {
+        creators.push(metaplex_token_metadata::state::Creator{
+            address : c.address,
+            verified : c.address == *owner.key,
+            share : c.share,
+        });
+    }
+
+    let metadata_instruction = if collection == Pubkey::default() {
+        create_metadata_accounts(
+            *token_metadata_program.key,
+            *metadata.key,
+            *mint.key,
+            *owner.key,
+            *owner.key,
+            *owner.key,
+            data.name,
+            data.symbol,
+            data.uri,
+            Some(creators),
+            data.seller_fee_basis_points,
+            true,
+            data.is_mutable,
+        )
+    } else {
+        if *collection_mint.key != collection {
+            return Err(PoolError::InvalidCollection.into());
+        }
+        create_metadata_accounts_v2(
+            *token_metadata_program.key,
+            *metadata.key,
+            *mint.key,
+            *owner.key,
+            *owner.key,
+            *owner.key,
+            DataV2{
+                name : data.name,
+                symbol : data.symbol,
+                uri : data.uri,
+                seller_fee_basis_points : data.seller_fee_basis_points,
+                creators : Some(creators.iter().map(|c| Creator{
+                    address : c.address,
+                    verified : c.verified,
+                    share : c.share,
+                }).collect()),
+                collection : Some(CollectionV2{
+                    verified : false,
+                    key : collection,
+                }),
+                uses : None,
+            },
+            data.is_mutable,
+        )?
+    };
+    invoke(
+        &metadata_instruction,
+        &[
+            metadata.clone(),
+            mint.clone(),
+            owner.clone(),
+            owner.clone(),
+            owner.clone(),
+            token_metadata_program.clone(),
+            token_program.clone(),
+            system_program.clone(),
+            rent.clone(),
+        ]
+    )?;
+
+    invoke_signed(
+        &sign_metadata(
+            *token_metadata_program.key,
+            *metadata.key,
+            *pool_signer.key,
+        ),
+        &[
+            metadata.clone(),
+            pool_signer.clone(),
+            token_metadata_program.clone(),
+        ],
+        &[&[b"pool_signer".as_ref(), pool.as_ref(), &[signer_bump]]]
+    )?;
+
+    invoke(
+        &create_master_edition(
+            *token_metadata_program.key,
+            *master_edition.key,
+            *mint.key,
+            *owner.key,
+            *owner.key,
+            *metadata.key,
+            *owner.key,
+            None,
+        ),
+        &[
+            master_edition.clone(),
+            mint.clone(),
+            owner.clone(),
+            owner.clone(),
+            owner.clone(),
+            metadata.clone(),
+            token_program.clone(),
+            system_program.clone(),
+            rent.clone(),
+        ]
+    )?;
+
+    if hidden {
+        invoke(
+            &update_metadata_accounts(
+                *token_metadata_program.key,
+                *metadata.key,
+                *owner.key,
+                Some(*pool_signer.key),
+                None,
+                None,
+            ),
+            &[
+                token_metadata_program.clone(),
+                metadata.clone(),
+                owner.clone(),
+            ]
+        )?;
+    }
+
+    if collection != Pubkey::default() {
+        invoke_signed(
+            &verify_collection(
+                *token_metadata_program.key,
+                *metadata.key,
+                *pool_signer.key,
+                *owner.key,
+                *collection_mint.key,
+                *collection_metadata.key,
+                *collection_master_edition.key,
+            ),
+            &[
+                metadata.clone(),
+                pool_signer.clone(),
+                owner.clone(),
+                collection_mint.clone(),
+                collection_metadata.clone(),
+                collection_master_edition.clone(),
+                token_metadata_program.clone(),
+            ],
+            &[&[b"pool_signer".as_ref(), pool.as_ref(), &[signer_bump]]]
+        )?;
+    }
+    Ok(())
 }
\ No newline at end of file
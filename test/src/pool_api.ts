@@ -1,17 +1,11 @@
 import {
   Connection,
   Keypair,
-  Signer,
   PublicKey,
-  Transaction,
-  TransactionSignature,
-  ConfirmOptions,
-  sendAndConfirmRawTransaction,
-  RpcResponseAndContext,
-  SimulatedTransactionResponse,
-  Commitment,
-  LAMPORTS_PER_SOL,
+  AccountMeta,
+  SystemProgram,
   SYSVAR_RENT_PUBKEY,
+  SYSVAR_CLOCK_PUBKEY,
 } from "@solana/web3.js";
 import * as splToken from "@solana/spl-token";
 import fs from "fs";
@@ -19,14 +13,146 @@ import * as anchor from "@project-serum/anchor";
 
 import { getProgramId } from "./utils";
 
-const sleep = (ms: number) => {
-  return new Promise((resolve) => setTimeout(resolve, ms));
-};
-
 export let programId = getProgramId("program");
-let metadataProgramId = getProgramId("metadata_program");
+export let metadataProgramId = getProgramId("metadata_program");
 const idl = JSON.parse(fs.readFileSync("src/solana_anchor.json", "utf8"));
 
+const BPF_LOADER_UPGRADEABLE = new PublicKey(
+  "BPFLoaderUpgradeab1e11111111111111111111111"
+);
+
+// Role bits checked by assert_pool_role.
+export const ROLE_WHITELIST_MANAGER = 1;
+export const ROLE_PRICING_MANAGER = 2;
+export const ROLE_SALE_CONTROLLER = 4;
+export const ROLE_TREASURER = 8;
+
+export const PHASE_WHITELIST = 0;
+export const PHASE_PUBLIC = 1;
+
+// Must match CONFIG_LINES_SIZE and CONFIG_LINE_SIZE in lib.rs.
+const MAX_NAME_LENGTH = 32;
+const MAX_URI_LENGTH = 200;
+const MAX_SYMBOL_LENGTH = 10;
+const MAX_CREATOR_NUM = 5;
+const CONFIG_LINE_SIZE = 4 + MAX_NAME_LENGTH + 4 + MAX_URI_LENGTH;
+const CONFIG_LINES_SIZE =
+  32 + 4 + MAX_SYMBOL_LENGTH + 2 + 4 + (32 + 1 + 1) * MAX_CREATOR_NUM + 1 + 1 + 4 + 4 + 4;
+
+// Instructions throw on failure so the scenarios in index.ts can tell a
+// rejection from a success.
+export function loadProgram(conn: Connection, signer: Keypair) {
+  let wallet = new anchor.Wallet(signer);
+  let provider = new anchor.Provider(conn, wallet, {
+    commitment: "confirmed",
+    preflightCommitment: "confirmed",
+  });
+  return new anchor.Program(idl, programId, provider);
+}
+
+const pda = (seeds: Buffer[], program = programId) =>
+  PublicKey.findProgramAddress(seeds, program);
+
+export const findConfig = () => pda([Buffer.from("config")]);
+
+export const findPoolRegistry = (owner: PublicKey) =>
+  pda([Buffer.from("pool_registry"), owner.toBuffer()]);
+
+export const findRegisteredPool = (owner: PublicKey, index: number) =>
+  pda([
+    Buffer.from("pool"),
+    owner.toBuffer(),
+    new anchor.BN(index).toArrayLike(Buffer, "le", 8),
+  ]);
+
+export const findClient = (pool: PublicKey, wallet: PublicKey) =>
+  pda([programId.toBuffer(), pool.toBuffer(), wallet.toBuffer()]);
+
+export const findPoolRole = (pool: PublicKey, member: PublicKey) =>
+  pda([Buffer.from("pool_role"), pool.toBuffer(), member.toBuffer()]);
+
+export const findPoolSigner = (pool: PublicKey) =>
+  pda([Buffer.from("pool_signer"), pool.toBuffer()]);
+
+export const findTreasury = (pool: PublicKey) =>
+  pda([Buffer.from("treasury"), pool.toBuffer()]);
+
+export const findPoolAuthority = (pool: PublicKey) =>
+  pda([Buffer.from("pool_authority"), pool.toBuffer()]);
+
+export const findAuthoritySigner = (poolAuthority: PublicKey) =>
+  pda([Buffer.from("authority_signer"), poolAuthority.toBuffer()]);
+
+export const findMintRecord = (pool: PublicKey, wallet: PublicKey) =>
+  pda([Buffer.from("mint_record"), pool.toBuffer(), wallet.toBuffer()]);
+export const findPublicMintRecord = (pool: PublicKey, wallet: PublicKey) =>
+  pda([Buffer.from("public_mint_record"), pool.toBuffer(), wallet.toBuffer()]);
+
+export const findMetadataExtended = (mint: PublicKey, pool: PublicKey) =>
+  pda([mint.toBuffer(), pool.toBuffer(), programId.toBuffer()]);
+
+export const findSaleManager = (pool: PublicKey, mint: PublicKey) =>
+  pda([pool.toBuffer(), mint.toBuffer()]);
+
+export const findMigratedPool = (legacyPool: PublicKey) =>
+  pda([Buffer.from("migrated_pool"), legacyPool.toBuffer()]);
+
+export const findMetadata = async (mint: PublicKey) =>
+  (
+    await pda(
+      [Buffer.from("metadata"), metadataProgramId.toBuffer(), mint.toBuffer()],
+      metadataProgramId
+    )
+  )[0];
+
+export const findMasterEdition = async (mint: PublicKey) =>
+  (
+    await pda(
+      [
+        Buffer.from("metadata"),
+        metadataProgramId.toBuffer(),
+        mint.toBuffer(),
+        Buffer.from("edition"),
+      ],
+      metadataProgramId
+    )
+  )[0];
+
+export const findEditionMarker = async (mint: PublicKey, edition: number) =>
+  (
+    await pda(
+      [
+        Buffer.from("metadata"),
+        metadataProgramId.toBuffer(),
+        mint.toBuffer(),
+        Buffer.from("edition"),
+        Buffer.from(Math.floor(edition / 248).toString()),
+      ],
+      metadataProgramId
+    )
+  )[0];
+
+// Role accounts are only read when the signer is not the pool owner, so the
+// derived address is passed whether or not the role exists.
+const poolRoleOf = async (pool: PublicKey, member: PublicKey) =>
+  (await findPoolRole(pool, member))[0];
+
+// A fresh 0-decimal mint with one empty token account, ready to be minted as an NFT.
+export async function createNftMint(conn: Connection, owner: Keypair) {
+  let mint = await splToken.Token.createMint(
+    conn,
+    owner,
+    owner.publicKey,
+    null,
+    0,
+    splToken.TOKEN_PROGRAM_ID
+  );
+  let tokenAccount = await mint.createAccount(owner.publicKey);
+  return { mint, tokenAccount };
+}
+
+/* Pools and program config */
+
 export async function initPool(
   conn: Connection,
   owner: Keypair,
@@ -34,76 +160,422 @@ export async function initPool(
   sale_mint: PublicKey
 ) {
   console.log("+ initPool");
-  let wallet = new anchor.Wallet(owner);
-  let provider = new anchor.Provider(
-    conn,
-    wallet,
-    anchor.Provider.defaultOptions()
+  const program = loadProgram(conn, owner);
+  return await program.rpc.initPool({
+    accounts: {
+      pool: pool.publicKey,
+      owner: owner.publicKey,
+      saleMint: sale_mint,
+      systemProgram: SystemProgram.programId,
+    },
+    signers: [owner, pool],
+  });
+}
+
+export async function initPoolRegistry(conn: Connection, owner: Keypair) {
+  console.log("+ initPoolRegistry");
+  const program = loadProgram(conn, owner);
+  let [poolRegistry, bump] = await findPoolRegistry(owner.publicKey);
+  return await program.rpc.initPoolRegistry(bump, {
+    accounts: {
+      poolRegistry: poolRegistry,
+      owner: owner.publicKey,
+      systemProgram: SystemProgram.programId,
+    },
+    signers: [owner],
+  });
+}
+
+// Creates the owner's next registered pool and returns its address.
+export async function initRegisteredPool(
+  conn: Connection,
+  owner: Keypair,
+  sale_mint: PublicKey,
+  index?: number
+) {
+  console.log("+ initRegisteredPool");
+  const program = loadProgram(conn, owner);
+  let [poolRegistry] = await findPoolRegistry(owner.publicKey);
+  if (index === undefined) {
+    index = (await program.account.poolRegistry.fetch(poolRegistry)).count.toNumber();
+  }
+  let [pool, bump] = await findRegisteredPool(owner.publicKey, index);
+  await program.rpc.initRegisteredPool(bump, {
+    accounts: {
+      pool: pool,
+      poolRegistry: poolRegistry,
+      owner: owner.publicKey,
+      saleMint: sale_mint,
+      systemProgram: SystemProgram.programId,
+    },
+    signers: [owner],
+  });
+  return pool;
+}
+
+export async function closePoolRegistry(conn: Connection, owner: Keypair, registryOwner?: PublicKey) {
+  console.log("+ closePoolRegistry");
+  const program = loadProgram(conn, owner);
+  let [poolRegistry] = await findPoolRegistry(registryOwner || owner.publicKey);
+  return await program.rpc.closePoolRegistry({
+    accounts: {
+      owner: owner.publicKey,
+      poolRegistry: poolRegistry,
+    },
+    signers: [owner],
+  });
+}
+
+// Must be signed by the program's upgrade authority, i.e. the deploy keypair.
+export async function initProgramConfig(conn: Connection, admin: Keypair) {
+  console.log("+ initProgramConfig");
+  const program = loadProgram(conn, admin);
+  let [config, bump] = await findConfig();
+  let [programData] = await PublicKey.findProgramAddress(
+    [programId.toBuffer()],
+    BPF_LOADER_UPGRADEABLE
   );
-  const program = new anchor.Program(idl, programId, provider);
-  try {
-    await program.rpc.initPool({
-      accounts: {
-        pool: pool.publicKey,
-        owner: owner.publicKey,
-        saleMint: sale_mint,
-        systemProgram: anchor.web3.SystemProgram.programId,
-      },
-      signers: [owner, pool],
-    });
-  } catch (err) {
-    console.log(err);
+  return await program.rpc.initProgramConfig(bump, {
+    accounts: {
+      config: config,
+      admin: admin.publicKey,
+      program: programId,
+      programData: programData,
+      systemProgram: SystemProgram.programId,
+    },
+    signers: [admin],
+  });
+}
+
+export async function programConfigExists(conn: Connection) {
+  let [config] = await findConfig();
+  return (await conn.getAccountInfo(config)) !== null;
+}
+
+export async function setProgramPaused(conn: Connection, admin: Keypair, paused: boolean) {
+  console.log("+ setProgramPaused");
+  const program = loadProgram(conn, admin);
+  let [config] = await findConfig();
+  return await program.rpc.setProgramPaused(paused, {
+    accounts: {
+      config: config,
+      admin: admin.publicKey,
+    },
+    signers: [admin],
+  });
+}
+
+export async function setPoolPaused(conn: Connection, owner: Keypair, pool: PublicKey, paused: boolean) {
+  console.log("+ setPoolPaused");
+  const program = loadProgram(conn, owner);
+  return await program.rpc.setPoolPaused(paused, {
+    accounts: {
+      pool: pool,
+      owner: owner.publicKey,
+      poolRole: await poolRoleOf(pool, owner.publicKey),
+    },
+    signers: [owner],
+  });
+}
+
+export async function closePool(conn: Connection, owner: Keypair, pool: PublicKey) {
+  console.log("+ closePool");
+  const program = loadProgram(conn, owner);
+  let [treasury] = await findTreasury(pool);
+  let [poolAuthority] = await findPoolAuthority(pool);
+  // Only a registered pool has a registry to decrement; any other pool passes
+  // the owner's registry address, which is not read.
+  let poolRegistry = (await program.account.pool.fetch(pool)).poolRegistry;
+  if (poolRegistry.equals(PublicKey.default)) {
+    [poolRegistry] = await findPoolRegistry(owner.publicKey);
   }
-  console.log("- end");
-  await sleep(100);
-  // const account = await program.account.pool.fetch(pool.publicKey)
-  // console.log(account)
+  return await program.rpc.closePool({
+    accounts: {
+      owner: owner.publicKey,
+      pool: pool,
+      treasury: treasury,
+      poolAuthority: poolAuthority,
+      poolRegistry: poolRegistry,
+      recipient: owner.publicKey,
+    },
+    signers: [owner],
+  });
+}
+
+/* Ownership */
+
+export async function setAuthority(conn: Connection, owner: Keypair, pool: PublicKey, newOwner: Keypair) {
+  console.log("+ setAuthority");
+  const program = loadProgram(conn, owner);
+  return await program.rpc.setAuthority({
+    accounts: {
+      pool: pool,
+      owner: owner.publicKey,
+      newOwner: newOwner.publicKey,
+    },
+    signers: [owner, newOwner],
+  });
+}
+
+export async function proposePoolOwner(conn: Connection, owner: Keypair, pool: PublicKey, newOwner: PublicKey) {
+  console.log("+ proposePoolOwner");
+  const program = loadProgram(conn, owner);
+  return await program.rpc.proposePoolOwner({
+    accounts: {
+      pool: pool,
+      owner: owner.publicKey,
+      newOwner: newOwner,
+    },
+    signers: [owner],
+  });
+}
+
+export async function acceptPoolOwner(conn: Connection, newOwner: Keypair, pool: PublicKey) {
+  console.log("+ acceptPoolOwner");
+  const program = loadProgram(conn, newOwner);
+  return await program.rpc.acceptPoolOwner({
+    accounts: {
+      pool: pool,
+      newOwner: newOwner.publicKey,
+    },
+    signers: [newOwner],
+  });
+}
+
+export async function cancelPoolOwnerProposal(conn: Connection, owner: Keypair, pool: PublicKey) {
+  console.log("+ cancelPoolOwnerProposal");
+  const program = loadProgram(conn, owner);
+  return await program.rpc.cancelPoolOwnerProposal({
+    accounts: {
+      pool: pool,
+      owner: owner.publicKey,
+    },
+    signers: [owner],
+  });
+}
+
+/* Multisig pool authority */
+
+export async function initPoolAuthority(
+  conn: Connection,
+  owner: Keypair,
+  pool: PublicKey,
+  signers: PublicKey[],
+  threshold: number
+) {
+  console.log("+ initPoolAuthority");
+  const program = loadProgram(conn, owner);
+  let [poolAuthority, bump] = await findPoolAuthority(pool);
+  let [authoritySigner] = await findAuthoritySigner(poolAuthority);
+  return await program.rpc.initPoolAuthority(bump, signers, threshold, {
+    accounts: {
+      pool: pool,
+      owner: owner.publicKey,
+      poolAuthority: poolAuthority,
+      authoritySigner: authoritySigner,
+      systemProgram: SystemProgram.programId,
+    },
+    signers: [owner],
+  });
+}
+
+// Only the authority signer PDA can sign this, so outside of an executed
+// proposal it always fails; see setAuthoritySignersProposal.
+export async function setAuthoritySigners(
+  conn: Connection,
+  payer: Keypair,
+  pool: PublicKey,
+  signers: PublicKey[],
+  threshold: number
+) {
+  console.log("+ setAuthoritySigners");
+  const program = loadProgram(conn, payer);
+  let [poolAuthority] = await findPoolAuthority(pool);
+  let [authoritySigner] = await findAuthoritySigner(poolAuthority);
+  let ix = program.instruction.setAuthoritySigners(signers, threshold, {
+    accounts: {
+      poolAuthority: poolAuthority,
+      authoritySigner: authoritySigner,
+    },
+  });
+  return await program.provider.send(new anchor.web3.Transaction().add(ix), [payer]);
+}
+
+// Builds the accounts and data of a set_authority_signers call for a proposal.
+export async function setAuthoritySignersProposal(
+  conn: Connection,
+  payer: Keypair,
+  pool: PublicKey,
+  signers: PublicKey[],
+  threshold: number
+) {
+  const program = loadProgram(conn, payer);
+  let [poolAuthority] = await findPoolAuthority(pool);
+  let [authoritySigner] = await findAuthoritySigner(poolAuthority);
+  return {
+    accounts: [
+      { pubkey: poolAuthority, isSigner: false, isWritable: true },
+      { pubkey: authoritySigner, isSigner: true, isWritable: false },
+    ],
+    data: program.coder.instruction.encode("setAuthoritySigners", {
+      signers: signers,
+      threshold: threshold,
+    }),
+  };
+}
+
+export async function createAuthorityProposal(
+  conn: Connection,
+  proposer: Keypair,
+  pool: PublicKey,
+  proposal: Keypair,
+  accounts: { pubkey: PublicKey; isSigner: boolean; isWritable: boolean }[],
+  data: Buffer
+) {
+  console.log("+ createAuthorityProposal");
+  const program = loadProgram(conn, proposer);
+  let [poolAuthority] = await findPoolAuthority(pool);
+  return await program.rpc.createAuthorityProposal(accounts, data, {
+    accounts: {
+      poolAuthority: poolAuthority,
+      proposal: proposal.publicKey,
+      proposer: proposer.publicKey,
+      systemProgram: SystemProgram.programId,
+    },
+    signers: [proposer, proposal],
+  });
+}
+
+export async function approveAuthorityProposal(
+  conn: Connection,
+  signer: Keypair,
+  pool: PublicKey,
+  proposal: PublicKey
+) {
+  console.log("+ approveAuthorityProposal");
+  const program = loadProgram(conn, signer);
+  let [poolAuthority] = await findPoolAuthority(pool);
+  return await program.rpc.approveAuthorityProposal({
+    accounts: {
+      poolAuthority: poolAuthority,
+      proposal: proposal,
+      signer: signer.publicKey,
+    },
+    signers: [signer],
+  });
 }
 
+export async function executeAuthorityProposal(
+  conn: Connection,
+  payer: Keypair,
+  pool: PublicKey,
+  proposal: PublicKey
+) {
+  console.log("+ executeAuthorityProposal");
+  const program = loadProgram(conn, payer);
+  let [poolAuthority] = await findPoolAuthority(pool);
+  let [authoritySigner] = await findAuthoritySigner(poolAuthority);
+  let data = await program.account.authorityProposal.fetch(proposal);
+  // The proposed instruction runs against this program, so its accounts and
+  // the program itself go in as remaining accounts.
+  let remainingAccounts: AccountMeta[] = data.accounts.map((a: any) => ({
+    pubkey: a.pubkey,
+    isSigner: false,
+    isWritable: a.isWritable,
+  }));
+  remainingAccounts.push({ pubkey: programId, isSigner: false, isWritable: false });
+  return await program.rpc.executeAuthorityProposal({
+    accounts: {
+      poolAuthority: poolAuthority,
+      authoritySigner: authoritySigner,
+      proposal: proposal,
+    },
+    remainingAccounts: remainingAccounts,
+    signers: [payer],
+  });
+}
+
+/* Roles */
+
+export async function setPoolRole(
+  conn: Connection,
+  owner: Keypair,
+  pool: PublicKey,
+  member: PublicKey,
+  roles: number
+) {
+  console.log("+ setPoolRole");
+  const program = loadProgram(conn, owner);
+  let [poolRole, bump] = await findPoolRole(pool, member);
+  return await program.rpc.setPoolRole(bump, roles, {
+    accounts: {
+      poolRole: poolRole,
+      pool: pool,
+      owner: owner.publicKey,
+      member: member,
+      systemProgram: SystemProgram.programId,
+    },
+    signers: [owner],
+  });
+}
+
+export async function updatePoolRole(
+  conn: Connection,
+  owner: Keypair,
+  pool: PublicKey,
+  member: PublicKey,
+  roles: number
+) {
+  console.log("+ updatePoolRole");
+  const program = loadProgram(conn, owner);
+  return await program.rpc.updatePoolRole(roles, {
+    accounts: {
+      poolRole: await poolRoleOf(pool, member),
+      pool: pool,
+      owner: owner.publicKey,
+    },
+    signers: [owner],
+  });
+}
+
+export async function closePoolRole(conn: Connection, owner: Keypair, pool: PublicKey, member: PublicKey) {
+  console.log("+ closePoolRole");
+  const program = loadProgram(conn, owner);
+  return await program.rpc.closePoolRole({
+    accounts: {
+      poolRole: await poolRoleOf(pool, member),
+      pool: pool,
+      owner: owner.publicKey,
+    },
+    signers: [owner],
+  });
+}
+
+/* Whitelist */
+
 export async function setWhitelist(
   conn: Connection,
   pool: PublicKey,
   owner: Keypair,
   bidder: PublicKey,
   amount: number,
-  whitelisted: Boolean
+  whitelisted: Boolean,
+  tier: number = 0
 ) {
   console.log("+ setWhitelist");
-  let wallet = new anchor.Wallet(owner);
-  let provider = new anchor.Provider(
-    conn,
-    wallet,
-    anchor.Provider.defaultOptions()
-  );
-  const program = new anchor.Program(idl, programId, provider);
-  let [client, bump] = await PublicKey.findProgramAddress(
-    [programId.toBuffer(), pool.toBuffer(), bidder.toBuffer()],
-    programId
-  );
-
-  try {
-    await program.rpc.setWhitelist(
-      new anchor.BN(bump),
-      new anchor.BN(amount),
-      whitelisted,
-      {
-        accounts: {
-          client: client,
-          pool: pool,
-          owner: owner.publicKey,
-          bidder: bidder,
-          systemProgram: anchor.web3.SystemProgram.programId,
-        },
-        signers: [owner],
-      }
-    );
-  } catch (err) {
-    console.log(err);
-  }
-  console.log("- end");
-  await sleep(100);
-  // const account = await program.account.client.fetch(client)
-  // console.log(account)
+  const program = loadProgram(conn, owner);
+  let [client, bump] = await findClient(pool, bidder);
+  return await program.rpc.setWhitelist(bump, new anchor.BN(amount), whitelisted, tier, {
+    accounts: {
+      client: client,
+      pool: pool,
+      owner: owner.publicKey,
+      poolRole: await poolRoleOf(pool, owner.publicKey),
+      bidder: bidder,
+      systemProgram: SystemProgram.programId,
+    },
+    signers: [owner],
+  });
 }
 
 export async function updateWhitelist(
@@ -112,39 +584,127 @@ export async function updateWhitelist(
   owner: Keypair,
   bidder: PublicKey,
   amount: number,
-  whitelisted: Boolean
+  whitelisted: Boolean,
+  tier: number = 0
 ) {
   console.log("+ updateWhitelist");
-  let wallet = new anchor.Wallet(owner);
-  let provider = new anchor.Provider(
-    conn,
-    wallet,
-    anchor.Provider.defaultOptions()
-  );
-  const program = new anchor.Program(idl, programId, provider);
-  let [client, bump] = await PublicKey.findProgramAddress(
-    [programId.toBuffer(), pool.toBuffer(), bidder.toBuffer()],
-    programId
-  );
+  const program = loadProgram(conn, owner);
+  let [client] = await findClient(pool, bidder);
+  return await program.rpc.updateWhitelist(new anchor.BN(amount), whitelisted, tier, {
+    accounts: {
+      client: client,
+      pool: pool,
+      owner: owner.publicKey,
+      poolRole: await poolRoleOf(pool, owner.publicKey),
+    },
+    signers: [owner],
+  });
+}
 
-  try {
-    await program.rpc.updateWhitelist(new anchor.BN(amount), whitelisted, {
-      accounts: {
-        client: client,
-        pool: pool,
-        owner: owner.publicKey,
-      },
-      signers: [owner],
+export type WhitelistEntry = {
+  bidder: PublicKey;
+  amount: number;
+  whitelisted: boolean;
+  tier: number;
+};
+
+// `clientCount` lets a caller pass fewer client accounts than entries.
+async function whitelistBatch(pool: PublicKey, entries: WhitelistEntry[], clientCount?: number) {
+  let args = [];
+  let remainingAccounts: AccountMeta[] = [];
+  for (let entry of entries) {
+    let [client, bump] = await findClient(pool, entry.bidder);
+    args.push({
+      bidder: entry.bidder,
+      amount: new anchor.BN(entry.amount),
+      whitelisted: entry.whitelisted,
+      bump: bump,
+      tier: entry.tier,
     });
-  } catch (err) {
-    console.log(err);
+    remainingAccounts.push({ pubkey: client, isSigner: false, isWritable: true });
   }
-  console.log("- end");
-  await sleep(100);
-  // const account = await program.account.client.fetch(client)
-  // console.log(account)
+  return { args, remainingAccounts: remainingAccounts.slice(0, clientCount ?? entries.length) };
+}
+
+export async function setWhitelistBatch(
+  conn: Connection,
+  owner: Keypair,
+  pool: PublicKey,
+  entries: WhitelistEntry[],
+  clientCount?: number
+) {
+  console.log("+ setWhitelistBatch");
+  const program = loadProgram(conn, owner);
+  let { args, remainingAccounts } = await whitelistBatch(pool, entries, clientCount);
+  return await program.rpc.setWhitelistBatch(args, {
+    accounts: {
+      pool: pool,
+      owner: owner.publicKey,
+      poolRole: await poolRoleOf(pool, owner.publicKey),
+      systemProgram: SystemProgram.programId,
+      rent: SYSVAR_RENT_PUBKEY,
+    },
+    remainingAccounts: remainingAccounts,
+    signers: [owner],
+  });
+}
+
+export async function updateWhitelistBatch(
+  conn: Connection,
+  owner: Keypair,
+  pool: PublicKey,
+  entries: WhitelistEntry[],
+  clientCount?: number
+) {
+  console.log("+ updateWhitelistBatch");
+  const program = loadProgram(conn, owner);
+  let { args, remainingAccounts } = await whitelistBatch(pool, entries, clientCount);
+  return await program.rpc.updateWhitelistBatch(args, {
+    accounts: {
+      pool: pool,
+      owner: owner.publicKey,
+      poolRole: await poolRoleOf(pool, owner.publicKey),
+    },
+    remainingAccounts: remainingAccounts,
+    signers: [owner],
+  });
+}
+
+export async function closeClient(
+  conn: Connection,
+  authority: Keypair,
+  pool: PublicKey,
+  wallet: PublicKey
+) {
+  console.log("+ closeClient");
+  const program = loadProgram(conn, authority);
+  let [client] = await findClient(pool, wallet);
+  return await program.rpc.closeClient({
+    accounts: {
+      authority: authority.publicKey,
+      pool: pool,
+      client: client,
+      recipient: authority.publicKey,
+    },
+    signers: [authority],
+  });
+}
+
+export async function setMerkleRoot(conn: Connection, owner: Keypair, pool: PublicKey, root: number[]) {
+  console.log("+ setMerkleRoot");
+  const program = loadProgram(conn, owner);
+  return await program.rpc.setMerkleRoot(root, {
+    accounts: {
+      pool: pool,
+      owner: owner.publicKey,
+      poolRole: await poolRoleOf(pool, owner.publicKey),
+    },
+    signers: [owner],
+  });
 }
 
+/* Sale settings */
+
 export async function controlPresaleLive(
   conn: Connection,
   pool: PublicKey,
@@ -152,29 +712,548 @@ export async function controlPresaleLive(
   isLive: Boolean
 ) {
   console.log("+ controlPresaleLive");
-  let wallet = new anchor.Wallet(owner);
-  let provider = new anchor.Provider(
-    conn,
-    wallet,
-    anchor.Provider.defaultOptions()
+  const program = loadProgram(conn, owner);
+  return await program.rpc.controlPresaleLive(isLive, {
+    accounts: {
+      pool: pool,
+      owner: owner.publicKey,
+      poolRole: await poolRoleOf(pool, owner.publicKey),
+    },
+    signers: [owner],
+  });
+}
+
+export type MintPhase = { kind: number; startTs: number; endTs: number };
+
+export async function setMintPhases(conn: Connection, owner: Keypair, pool: PublicKey, phases: MintPhase[]) {
+  console.log("+ setMintPhases");
+  const program = loadProgram(conn, owner);
+  return await program.rpc.setMintPhases(
+    phases.map((p) => ({
+      kind: p.kind,
+      startTs: new anchor.BN(p.startTs),
+      endTs: new anchor.BN(p.endTs),
+    })),
+    {
+      accounts: {
+        pool: pool,
+        owner: owner.publicKey,
+        poolRole: await poolRoleOf(pool, owner.publicKey),
+      },
+      signers: [owner],
+    }
   );
-  const program = new anchor.Program(idl, programId, provider);
+}
+
+export type MintTier = { price: number; walletCap: number; startTs: number; endTs: number };
+
+export const encodeMintTiers = (tiers: MintTier[]) =>
+  tiers.map((t) => ({
+    price: new anchor.BN(t.price),
+    walletCap: new anchor.BN(t.walletCap),
+    startTs: new anchor.BN(t.startTs),
+    endTs: new anchor.BN(t.endTs),
+  }));
+
+export async function setMintTiers(conn: Connection, owner: Keypair, pool: PublicKey, tiers: MintTier[]) {
+  console.log("+ setMintTiers");
+  const program = loadProgram(conn, owner);
+  return await program.rpc.setMintTiers(encodeMintTiers(tiers), {
+    accounts: {
+      pool: pool,
+      owner: owner.publicKey,
+      poolRole: await poolRoleOf(pool, owner.publicKey),
+    },
+    signers: [owner],
+  });
+}
 
-  try {
-    await program.rpc.controlPresaleLive(isLive, {
+export async function setPublicMint(
+  conn: Connection,
+  owner: Keypair,
+  pool: PublicKey,
+  enabled: boolean,
+  walletLimit: number
+) {
+  console.log("+ setPublicMint");
+  const program = loadProgram(conn, owner);
+  return await program.rpc.setPublicMint(enabled, new anchor.BN(walletLimit), {
+    accounts: {
+      pool: pool,
+      owner: owner.publicKey,
+      poolRole: await poolRoleOf(pool, owner.publicKey),
+    },
+    signers: [owner],
+  });
+}
+
+export async function setMaxSupply(conn: Connection, owner: Keypair, pool: PublicKey, maxSupply: number) {
+  console.log("+ setMaxSupply");
+  const program = loadProgram(conn, owner);
+  return await program.rpc.setMaxSupply(new anchor.BN(maxSupply), {
+    accounts: {
+      pool: pool,
+      owner: owner.publicKey,
+      poolRole: await poolRoleOf(pool, owner.publicKey),
+    },
+    signers: [owner],
+  });
+}
+
+export async function setMintPrice(
+  conn: Connection,
+  owner: Keypair,
+  pool: PublicKey,
+  mintPrice: number,
+  mintPriceNative: boolean,
+  phasePrices: number[]
+) {
+  console.log("+ setMintPrice");
+  const program = loadProgram(conn, owner);
+  return await program.rpc.setMintPrice(
+    new anchor.BN(mintPrice),
+    mintPriceNative,
+    phasePrices.map((p) => new anchor.BN(p)),
+    {
       accounts: {
         pool: pool,
         owner: owner.publicKey,
+        poolRole: await poolRoleOf(pool, owner.publicKey),
       },
       signers: [owner],
-    });
-  } catch (err) {
-    console.log(err);
+    }
+  );
+}
+
+export async function withdrawTreasury(
+  conn: Connection,
+  owner: Keypair,
+  pool: PublicKey,
+  destination: PublicKey,
+  amount: number,
+  native: boolean,
+  treasuryToken?: PublicKey
+) {
+  console.log("+ withdrawTreasury");
+  const program = loadProgram(conn, owner);
+  let [treasury] = await findTreasury(pool);
+  return await program.rpc.withdrawTreasury(new anchor.BN(amount), native, {
+    accounts: {
+      owner: owner.publicKey,
+      pool: pool,
+      poolRole: await poolRoleOf(pool, owner.publicKey),
+      treasury: treasury,
+      treasuryToken: treasuryToken || treasury,
+      destination: destination,
+      tokenProgram: splToken.TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    },
+    signers: [owner],
+  });
+}
+
+export async function addPaymentMint(conn: Connection, owner: Keypair, pool: PublicKey, paymentMint: PublicKey) {
+  console.log("+ addPaymentMint");
+  const program = loadProgram(conn, owner);
+  return await program.rpc.addPaymentMint({
+    accounts: {
+      owner: owner.publicKey,
+      pool: pool,
+      poolRole: await poolRoleOf(pool, owner.publicKey),
+      paymentMint: paymentMint,
+    },
+    signers: [owner],
+  });
+}
+
+export async function removePaymentMint(conn: Connection, owner: Keypair, pool: PublicKey, paymentMint: PublicKey) {
+  console.log("+ removePaymentMint");
+  const program = loadProgram(conn, owner);
+  return await program.rpc.removePaymentMint(paymentMint, {
+    accounts: {
+      owner: owner.publicKey,
+      pool: pool,
+      poolRole: await poolRoleOf(pool, owner.publicKey),
+    },
+    signers: [owner],
+  });
+}
+
+export async function setMarketplaceFee(
+  conn: Connection,
+  owner: Keypair,
+  pool: PublicKey,
+  feeBasisPoints: number,
+  feeTreasury: PublicKey
+) {
+  console.log("+ setMarketplaceFee");
+  const program = loadProgram(conn, owner);
+  return await program.rpc.setMarketplaceFee(feeBasisPoints, {
+    accounts: {
+      owner: owner.publicKey,
+      pool: pool,
+      poolRole: await poolRoleOf(pool, owner.publicKey),
+      feeTreasury: feeTreasury,
+    },
+    signers: [owner],
+  });
+}
+
+/* Timelock */
+
+export async function setTimelockDelay(conn: Connection, owner: Keypair, pool: PublicKey, delay: number) {
+  console.log("+ setTimelockDelay");
+  const program = loadProgram(conn, owner);
+  return await program.rpc.setTimelockDelay(new anchor.BN(delay), {
+    accounts: {
+      owner: owner.publicKey,
+      pool: pool,
+    },
+    signers: [owner],
+  });
+}
+
+// `change` is a PoolChange variant, e.g. { marketplaceFee: { feeBasisPoints, feeTreasury } }.
+export async function queuePoolChange(
+  conn: Connection,
+  owner: Keypair,
+  pool: PublicKey,
+  queuedChange: Keypair,
+  change: any
+) {
+  console.log("+ queuePoolChange");
+  const program = loadProgram(conn, owner);
+  return await program.rpc.queuePoolChange(change, {
+    accounts: {
+      owner: owner.publicKey,
+      pool: pool,
+      poolRole: await poolRoleOf(pool, owner.publicKey),
+      queuedChange: queuedChange.publicKey,
+      clock: SYSVAR_CLOCK_PUBKEY,
+      systemProgram: SystemProgram.programId,
+    },
+    signers: [owner, queuedChange],
+  });
+}
+
+// `target` is the account the change writes to, for MaxPrice and SaleMint.
+export async function executePoolChange(
+  conn: Connection,
+  payer: Keypair,
+  pool: PublicKey,
+  queuedChange: PublicKey,
+  target?: PublicKey
+) {
+  console.log("+ executePoolChange");
+  const program = loadProgram(conn, payer);
+  let data = await program.account.queuedChange.fetch(queuedChange);
+  return await program.rpc.executePoolChange({
+    accounts: {
+      pool: pool,
+      queuedChange: queuedChange,
+      payer: data.payer,
+      target: target || pool,
+      clock: SYSVAR_CLOCK_PUBKEY,
+    },
+    signers: [payer],
+  });
+}
+
+export async function cancelPoolChange(conn: Connection, owner: Keypair, pool: PublicKey, queuedChange: PublicKey) {
+  console.log("+ cancelPoolChange");
+  const program = loadProgram(conn, owner);
+  let data = await program.account.queuedChange.fetch(queuedChange);
+  return await program.rpc.cancelPoolChange({
+    accounts: {
+      owner: owner.publicKey,
+      pool: pool,
+      queuedChange: queuedChange,
+      payer: data.payer,
+    },
+    signers: [owner],
+  });
+}
+
+/* Config lines, collection, hidden and edition modes */
+
+export type Creator = { address: PublicKey; verified: boolean; share: number };
+export type ConfigLine = { name: string; uri: string };
+
+// Allocates the config lines account (owned by the program) and initialises it.
+export async function initConfigLines(
+  conn: Connection,
+  owner: Keypair,
+  pool: PublicKey,
+  configLines: Keypair,
+  symbol: string,
+  sellerFeeBasisPoints: number,
+  creators: Creator[],
+  isMutable: boolean,
+  random: boolean,
+  maxLines: number,
+  allocatedLines: number = maxLines
+) {
+  console.log("+ initConfigLines");
+  const program = loadProgram(conn, owner);
+  let space = 8 + CONFIG_LINES_SIZE + CONFIG_LINE_SIZE * allocatedLines;
+  return await program.rpc.initConfigLines(
+    symbol,
+    sellerFeeBasisPoints,
+    creators,
+    isMutable,
+    random,
+    maxLines,
+    {
+      accounts: {
+        configLines: configLines.publicKey,
+        pool: pool,
+        owner: owner.publicKey,
+      },
+      instructions: [
+        SystemProgram.createAccount({
+          fromPubkey: owner.publicKey,
+          newAccountPubkey: configLines.publicKey,
+          space: space,
+          lamports: await conn.getMinimumBalanceForRentExemption(space),
+          programId: programId,
+        }),
+      ],
+      signers: [owner, configLines],
+    }
+  );
+}
+
+export async function addConfigLines(
+  conn: Connection,
+  owner: Keypair,
+  pool: PublicKey,
+  configLines: PublicKey,
+  index: number,
+  lines: ConfigLine[]
+) {
+  console.log("+ addConfigLines");
+  const program = loadProgram(conn, owner);
+  return await program.rpc.addConfigLines(index, lines, {
+    accounts: {
+      configLines: configLines,
+      pool: pool,
+      owner: owner.publicKey,
+    },
+    signers: [owner],
+  });
+}
+
+export async function closeConfigLines(conn: Connection, owner: Keypair, pool: PublicKey, configLines: PublicKey) {
+  console.log("+ closeConfigLines");
+  const program = loadProgram(conn, owner);
+  return await program.rpc.closeConfigLines({
+    accounts: {
+      configLines: configLines,
+      pool: pool,
+      owner: owner.publicKey,
+    },
+    signers: [owner],
+  });
+}
+
+export async function setCollection(conn: Connection, owner: Keypair, pool: PublicKey, collectionMint: PublicKey) {
+  console.log("+ setCollection");
+  const program = loadProgram(conn, owner);
+  let [poolSigner] = await findPoolSigner(pool);
+  return await program.rpc.setCollection({
+    accounts: {
+      pool: pool,
+      owner: owner.publicKey,
+      poolSigner: poolSigner,
+      collectionMint: collectionMint,
+      collectionMetadata: await findMetadata(collectionMint),
+      tokenMetadataProgram: metadataProgramId,
+    },
+    signers: [owner],
+  });
+}
+
+export async function releaseCollection(conn: Connection, owner: Keypair, pool: PublicKey, collectionMint: PublicKey) {
+  console.log("+ releaseCollection");
+  const program = loadProgram(conn, owner);
+  let [poolSigner] = await findPoolSigner(pool);
+  return await program.rpc.releaseCollection({
+    accounts: {
+      pool: pool,
+      owner: owner.publicKey,
+      poolSigner: poolSigner,
+      collectionMint: collectionMint,
+      collectionMetadata: await findMetadata(collectionMint),
+      tokenMetadataProgram: metadataProgramId,
+    },
+    signers: [owner],
+  });
+}
+
+export async function setHiddenMode(
+  conn: Connection,
+  owner: Keypair,
+  pool: PublicKey,
+  configLines: PublicKey,
+  name: string,
+  uri: string,
+  revealRoot: number[],
+  leafCount: number
+) {
+  console.log("+ setHiddenMode");
+  const program = loadProgram(conn, owner);
+  return await program.rpc.setHiddenMode(name, uri, revealRoot, new anchor.BN(leafCount), {
+    accounts: {
+      pool: pool,
+      owner: owner.publicKey,
+      configLines: configLines,
+    },
+    signers: [owner],
+  });
+}
+
+export async function reveal(
+  conn: Connection,
+  payer: Keypair,
+  pool: PublicKey,
+  mint: PublicKey,
+  holderToken: PublicKey,
+  line: ConfigLine,
+  proof: number[][]
+) {
+  console.log("+ reveal");
+  const program = loadProgram(conn, payer);
+  let [poolSigner] = await findPoolSigner(pool);
+  let [metadataExtended] = await findMetadataExtended(mint, pool);
+  return await program.rpc.reveal(line, proof, {
+    accounts: {
+      pool: pool,
+      poolSigner: poolSigner,
+      mint: mint,
+      metadataExtended: metadataExtended,
+      metadata: await findMetadata(mint),
+      holderToken: holderToken,
+      tokenMetadataProgram: metadataProgramId,
+    },
+    signers: [payer],
+  });
+}
+
+// The master token account must be held by the pool signer.
+export async function setEditionMint(
+  conn: Connection,
+  owner: Keypair,
+  pool: PublicKey,
+  editionMint: PublicKey,
+  editionToken: PublicKey
+) {
+  console.log("+ setEditionMint");
+  const program = loadProgram(conn, owner);
+  let [poolSigner] = await findPoolSigner(pool);
+  return await program.rpc.setEditionMint({
+    accounts: {
+      pool: pool,
+      owner: owner.publicKey,
+      poolRole: await poolRoleOf(pool, owner.publicKey),
+      poolSigner: poolSigner,
+      editionMint: editionMint,
+      editionToken: editionToken,
+      editionMetadata: await findMetadata(editionMint),
+    },
+    signers: [owner],
+  });
+}
+
+// Moves the master out of `editionToken` into `ownerToken`, which must belong to the pool owner.
+export async function clearEditionMint(
+  conn: Connection,
+  owner: Keypair,
+  pool: PublicKey,
+  editionToken: PublicKey,
+  ownerToken: PublicKey
+) {
+  console.log("+ clearEditionMint");
+  const program = loadProgram(conn, owner);
+  let [poolSigner] = await findPoolSigner(pool);
+  return await program.rpc.clearEditionMint({
+    accounts: {
+      pool: pool,
+      owner: owner.publicKey,
+      poolRole: await poolRoleOf(pool, owner.publicKey),
+      poolSigner: poolSigner,
+      editionToken: editionToken,
+      ownerToken: ownerToken,
+      tokenProgram: splToken.TOKEN_PROGRAM_ID,
+    },
+    signers: [owner],
+  });
+}
+
+/* Minting */
+
+export type MintPayment = { payerToken: PublicKey; treasuryToken: PublicKey };
+
+// Accounts shared by every mint instruction. Collection accounts are only read
+// when the pool has a collection, and the token accounts only for SPL prices.
+async function mintAccounts(
+  program: anchor.Program,
+  owner: Keypair,
+  pool: PublicKey,
+  configLines: PublicKey,
+  payment?: MintPayment
+) {
+  let poolData: any = await program.account.pool.fetch(pool);
+  let [config] = await findConfig();
+  let [poolSigner] = await findPoolSigner(pool);
+  let [treasury] = await findTreasury(pool);
+  let collectionMint: PublicKey = poolData.collectionMint;
+  return {
+    owner: owner.publicKey,
+    pool: pool,
+    config: config,
+    poolSigner: poolSigner,
+    collectionMint: collectionMint,
+    collectionMetadata: await findMetadata(collectionMint),
+    collectionMasterEdition: await findMasterEdition(collectionMint),
+    configLines: configLines,
+    treasury: treasury,
+    payerToken: payment ? payment.payerToken : owner.publicKey,
+    treasuryToken: payment ? payment.treasuryToken : treasury,
+    tokenMetadataProgram: metadataProgramId,
+    tokenProgram: splToken.TOKEN_PROGRAM_ID,
+    systemProgram: SystemProgram.programId,
+    rent: SYSVAR_RENT_PUBKEY,
+    clock: SYSVAR_CLOCK_PUBKEY,
+  };
+}
+
+// In edition mode every mint prints from the master held by the pool signer.
+async function editionAccounts(conn: Connection, program: anchor.Program, pool: PublicKey) {
+  let poolData: any = await program.account.pool.fetch(pool);
+  let editionMint: PublicKey = poolData.editionMint;
+  if (editionMint.equals(PublicKey.default)) {
+    return [];
   }
-  console.log("- end");
-  await sleep(100);
-  // const account = await program.account.pool.fetch(pool)
-  // console.log(account)
+  let [poolSigner] = await findPoolSigner(pool);
+  let masterEdition = await findMasterEdition(editionMint);
+  let masterTokens = await conn.getTokenAccountsByOwner(poolSigner, { mint: editionMint });
+  let masterEditionData = (await conn.getAccountInfo(masterEdition))!.data;
+  let edition = new anchor.BN(masterEditionData.slice(1, 9), "le").toNumber() + 1;
+  return [
+    { pubkey: await findMetadata(editionMint), isSigner: false, isWritable: true },
+    { pubkey: masterEdition, isSigner: false, isWritable: true },
+    { pubkey: masterTokens.value[0].pubkey, isSigner: false, isWritable: false },
+    { pubkey: await findEditionMarker(editionMint, edition), isSigner: false, isWritable: true },
+  ];
+}
+
+async function nftAccounts(mint: PublicKey, tokenAccount: PublicKey) {
+  return {
+    mint: mint,
+    tokenAccount: tokenAccount,
+    metadata: await findMetadata(mint),
+    masterEdition: await findMasterEdition(mint),
+  };
 }
 
 export async function mintNft(
@@ -183,70 +1262,136 @@ export async function mintNft(
   pool: PublicKey,
   mint: PublicKey,
   token_account: PublicKey,
-  data: any
+  configLines: PublicKey,
+  payment?: MintPayment
 ) {
   console.log("+ mintNFT");
-  let wallet = new anchor.Wallet(owner);
-  let provider = new anchor.Provider(
-    conn,
-    wallet,
-    anchor.Provider.defaultOptions()
-  );
-  const program = new anchor.Program(idl, programId, provider);
-  let client = (
-    await PublicKey.findProgramAddress(
-      [programId.toBuffer(), pool.toBuffer(), owner.publicKey.toBuffer()],
-      programId
-    )
-  )[0];
-  let metadata = (
-    await PublicKey.findProgramAddress(
-      [Buffer.from("metadata"), metadataProgramId.toBuffer(), mint.toBuffer()],
-      metadataProgramId
-    )
-  )[0];
-  let master_endition = (
-    await PublicKey.findProgramAddress(
-      [
-        Buffer.from("metadata"),
-        metadataProgramId.toBuffer(),
-        mint.toBuffer(),
-        Buffer.from("edition"),
-      ],
-      metadataProgramId
-    )
-  )[0];
-  let [metadata_extended, bump] = await PublicKey.findProgramAddress(
-    [mint.toBuffer(), pool.toBuffer(), programId.toBuffer()],
-    programId
-  );
-  try {
-    await program.rpc.mintNft(new anchor.BN(bump), data, {
-      accounts: {
-        owner: owner.publicKey,
-        pool: pool,
-        client: client,
-        mint: mint,
-        tokenAccount: token_account,
-        metadata: metadata,
-        masterEdition: master_endition,
-        metadataExtended: metadata_extended,
-        tokenMetadataProgram: metadataProgramId,
-        tokenProgram: splToken.TOKEN_PROGRAM_ID,
-        systemProgram: anchor.web3.SystemProgram.programId,
-        rent: SYSVAR_RENT_PUBKEY,
-      },
-      signers: [owner],
-    });
-  } catch (err) {
-    console.log(err);
+  const program = loadProgram(conn, owner);
+  let [client] = await findClient(pool, owner.publicKey);
+  let [metadata_extended, bump] = await findMetadataExtended(mint, pool);
+  return await program.rpc.mintNft(bump, {
+    accounts: {
+      ...(await mintAccounts(program, owner, pool, configLines, payment)),
+      ...(await nftAccounts(mint, token_account)),
+      client: client,
+      metadataExtended: metadata_extended,
+    },
+    remainingAccounts: await editionAccounts(conn, program, pool),
+    signers: [owner],
+  });
+}
+
+// `nfts` are fresh mints with their token accounts; `bumpCount` lets a caller
+// send fewer bumps than NFTs.
+export async function mintNftBatch(
+  conn: Connection,
+  owner: Keypair,
+  pool: PublicKey,
+  nfts: { mint: PublicKey; tokenAccount: PublicKey }[],
+  configLines: PublicKey,
+  bumpCount?: number,
+  payment?: MintPayment
+) {
+  console.log("+ mintNftBatch");
+  const program = loadProgram(conn, owner);
+  let [client] = await findClient(pool, owner.publicKey);
+  let bumps: number[] = [];
+  let remainingAccounts: AccountMeta[] = [];
+  for (let nft of nfts) {
+    let [metadataExtended, bump] = await findMetadataExtended(nft.mint, pool);
+    bumps.push(bump);
+    for (let pubkey of [
+      nft.mint,
+      nft.tokenAccount,
+      await findMetadata(nft.mint),
+      await findMasterEdition(nft.mint),
+      metadataExtended,
+    ]) {
+      remainingAccounts.push({ pubkey: pubkey, isSigner: false, isWritable: true });
+    }
+  }
+  return await program.rpc.mintNftBatch(Buffer.from(bumps.slice(0, bumpCount ?? bumps.length)), {
+    accounts: {
+      ...(await mintAccounts(program, owner, pool, configLines, payment)),
+      client: client,
+    },
+    remainingAccounts: remainingAccounts,
+    signers: [owner],
+  });
+}
+
+export async function mintNftWithProof(
+  conn: Connection,
+  owner: Keypair,
+  pool: PublicKey,
+  mint: PublicKey,
+  tokenAccount: PublicKey,
+  configLines: PublicKey,
+  allocation: number,
+  tier: number,
+  proof: number[][],
+  payment?: MintPayment
+) {
+  console.log("+ mintNftWithProof");
+  const program = loadProgram(conn, owner);
+  let [metadataExtended, bump] = await findMetadataExtended(mint, pool);
+  let [mintRecord, recordBump] = await findMintRecord(pool, owner.publicKey);
+  return await program.rpc.mintNftWithProof(bump, recordBump, new anchor.BN(allocation), tier, proof, {
+    accounts: {
+      ...(await mintAccounts(program, owner, pool, configLines, payment)),
+      ...(await nftAccounts(mint, tokenAccount)),
+      mintRecord: mintRecord,
+      metadataExtended: metadataExtended,
+    },
+    remainingAccounts: await editionAccounts(conn, program, pool),
+    signers: [owner],
+  });
+}
+
+// `mintRecord` overrides the canonical public mint record PDA and its bump.
+export async function mintNftPublic(
+  conn: Connection,
+  owner: Keypair,
+  pool: PublicKey,
+  mint: PublicKey,
+  tokenAccount: PublicKey,
+  configLines: PublicKey,
+  mintRecord?: [PublicKey, number],
+  payment?: MintPayment
+) {
+  console.log("+ mintNftPublic");
+  const program = loadProgram(conn, owner);
+  let [metadataExtended, bump] = await findMetadataExtended(mint, pool);
+  let [record, recordBump] = mintRecord || (await findPublicMintRecord(pool, owner.publicKey));
+  return await program.rpc.mintNftPublic(bump, recordBump, {
+    accounts: {
+      ...(await mintAccounts(program, owner, pool, configLines, payment)),
+      ...(await nftAccounts(mint, tokenAccount)),
+      mintRecord: record,
+      metadataExtended: metadataExtended,
+    },
+    remainingAccounts: await editionAccounts(conn, program, pool),
+    signers: [owner],
+  });
+}
+
+// Finds a valid public mint record address for a bump below the canonical one.
+export async function findNonCanonicalMintRecord(pool: PublicKey, wallet: PublicKey) {
+  let [, canonical] = await findPublicMintRecord(pool, wallet);
+  for (let bump = canonical - 1; bump >= 0; bump--) {
+    try {
+      let address = await PublicKey.createProgramAddress(
+        [Buffer.from("public_mint_record"), pool.toBuffer(), wallet.toBuffer(), Buffer.from([bump])],
+        programId
+      );
+      return [address, bump] as [PublicKey, number];
+    } catch (e) {}
   }
-  console.log("- end");
-  await sleep(1000);
-  // const account = await program.account.metadataExtended.fetch(metadata_extended)
-  // console.log(account)
+  throw new Error("no non-canonical mint record bump");
 }
 
+/* Marketplace */
+
 export async function setMaxPrice(
   conn: Connection,
   owner: Keypair,
@@ -255,36 +1400,18 @@ export async function setMaxPrice(
   max_price: number
 ) {
   console.log("+ setMaxPrice");
-  let wallet = new anchor.Wallet(owner);
-  let provider = new anchor.Provider(
-    conn,
-    wallet,
-    anchor.Provider.defaultOptions()
-  );
-  const program = new anchor.Program(idl, programId, provider);
-  let metadata_extended = (
-    await PublicKey.findProgramAddress(
-      [mint.toBuffer(), pool.toBuffer(), programId.toBuffer()],
-      programId
-    )
-  )[0];
-  try {
-    await program.rpc.setMaxPrice(new anchor.BN(max_price), {
-      accounts: {
-        owner: owner.publicKey,
-        pool: pool,
-        mint: mint,
-        metadataExtended: metadata_extended,
-      },
-      signers: [owner],
-    });
-  } catch (err) {
-    console.log(err);
-  }
-  console.log("- end");
-  await sleep(100);
-  // const account = await program.account.pool.fetch(pool)
-  // console.log(account)
+  const program = loadProgram(conn, owner);
+  let [metadata_extended] = await findMetadataExtended(mint, pool);
+  return await program.rpc.setMaxPrice(new anchor.BN(max_price), {
+    accounts: {
+      owner: owner.publicKey,
+      pool: pool,
+      poolRole: await poolRoleOf(pool, owner.publicKey),
+      mint: mint,
+      metadataExtended: metadata_extended,
+    },
+    signers: [owner],
+  });
 }
 
 export async function initSaleManager(
@@ -294,33 +1421,18 @@ export async function initSaleManager(
   nft_mint: PublicKey
 ) {
   console.log("+ initSaleManager");
-  let wallet = new anchor.Wallet(owner);
-  let provider = new anchor.Provider(
-    conn,
-    wallet,
-    anchor.Provider.defaultOptions()
-  );
-  const program = new anchor.Program(idl, programId, provider);
-  let [sale_manager, bump] = await PublicKey.findProgramAddress(
-    [pool.toBuffer(), nft_mint.toBuffer()],
-    programId
-  );
-  try {
-    await program.rpc.initSaleManager(new anchor.BN(bump), {
-      accounts: {
-        owner: owner.publicKey,
-        pool: pool,
-        nftMint: nft_mint,
-        saleManager: sale_manager,
-        systemProgram: anchor.web3.SystemProgram.programId,
-      },
-      signers: [owner],
-    });
-  } catch (err) {}
-  console.log("- end");
-  await sleep(1000);
-  // const account = await program.account.saleManager.fetch(sale_manager)
-  // console.log(account)
+  const program = loadProgram(conn, owner);
+  let [sale_manager, bump] = await findSaleManager(pool, nft_mint);
+  return await program.rpc.initSaleManager(bump, {
+    accounts: {
+      owner: owner.publicKey,
+      pool: pool,
+      nftMint: nft_mint,
+      saleManager: sale_manager,
+      systemProgram: SystemProgram.programId,
+    },
+    signers: [owner],
+  });
 }
 
 export async function sellNft(
@@ -334,63 +1446,30 @@ export async function sellNft(
   price: number
 ) {
   console.log("+ sellNft");
-  let wallet = new anchor.Wallet(owner);
-  let provider = new anchor.Provider(
-    conn,
-    wallet,
-    anchor.Provider.defaultOptions()
-  );
-  const program = new anchor.Program(idl, programId, provider);
-  let sale_manager = (
-    await PublicKey.findProgramAddress(
-      [pool.toBuffer(), nft_mint.toBuffer()],
-      programId
-    )
-  )[0];
-  let metadata = (
-    await PublicKey.findProgramAddress(
-      [
-        Buffer.from("metadata"),
-        metadataProgramId.toBuffer(),
-        nft_mint.toBuffer(),
-      ],
-      metadataProgramId
-    )
-  )[0];
-  let metadata_extended = (
-    await PublicKey.findProgramAddress(
-      [nft_mint.toBuffer(), pool.toBuffer(), programId.toBuffer()],
-      programId
-    )
-  )[0];
+  const program = loadProgram(conn, owner);
+  let [config] = await findConfig();
+  let [sale_manager] = await findSaleManager(pool, nft_mint);
+  let [metadata_extended] = await findMetadataExtended(nft_mint, pool);
   let sale_pot = Keypair.generate();
-
-  try {
-    await program.rpc.sellNft(new anchor.BN(price), {
-      accounts: {
-        owner: owner.publicKey,
-        pool: pool,
-        nftMint: nft_mint,
-        metadata: metadata,
-        metadataExtended: metadata_extended,
-        saleManager: sale_manager,
-        salePot: sale_pot.publicKey,
-        nftSellerToken: nft_seller_token,
-        nftManagerToken: nft_manager_token,
-        managerPot: manager_pot,
-        tokenMetadataProgram: metadataProgramId,
-        tokenProgram: splToken.TOKEN_PROGRAM_ID,
-        systemProgram: anchor.web3.SystemProgram.programId,
-      },
-      signers: [owner, sale_pot],
-    });
-  } catch (err) {
-    console.log(err);
-  }
-  console.log("- end");
-  await sleep(1000);
-  // const account = await program.account.saleManager.fetch(sale_manager)
-  // console.log(account)
+  return await program.rpc.sellNft(new anchor.BN(price), {
+    accounts: {
+      owner: owner.publicKey,
+      pool: pool,
+      config: config,
+      nftMint: nft_mint,
+      metadata: await findMetadata(nft_mint),
+      metadataExtended: metadata_extended,
+      saleManager: sale_manager,
+      salePot: sale_pot.publicKey,
+      nftSellerToken: nft_seller_token,
+      nftManagerToken: nft_manager_token,
+      managerPot: manager_pot,
+      tokenMetadataProgram: metadataProgramId,
+      tokenProgram: splToken.TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    },
+    signers: [owner, sale_pot],
+  });
 }
 
 export async function buyNft(
@@ -398,157 +1477,313 @@ export async function buyNft(
   owner: Keypair,
   pool: PublicKey,
   nft_mint: PublicKey,
-  // nft_manager_token : PublicKey,
   nft_bidder_token: PublicKey,
-  // manager_pot : PublicKey,
-  bidder_token: PublicKey
+  bidder_token: PublicKey,
+  treasury_token?: PublicKey
 ) {
   console.log("+ buyNft");
-  let wallet = new anchor.Wallet(owner);
-  let provider = new anchor.Provider(
-    conn,
-    wallet,
-    anchor.Provider.defaultOptions()
-  );
-  const program = new anchor.Program(idl, programId, provider);
-  let sale_manager = (
-    await PublicKey.findProgramAddress(
-      [pool.toBuffer(), nft_mint.toBuffer()],
-      programId
-    )
-  )[0];
-  let metadata = (
-    await PublicKey.findProgramAddress(
-      [
-        Buffer.from("metadata"),
-        metadataProgramId.toBuffer(),
-        nft_mint.toBuffer(),
-      ],
-      metadataProgramId
-    )
-  )[0];
+  const program = loadProgram(conn, owner);
+  let [config] = await findConfig();
+  let [sale_manager] = await findSaleManager(pool, nft_mint);
   let sale_manager_data = await program.account.saleManager.fetch(sale_manager);
-  let sale_pot_data = await program.account.salePot.fetch(
-    sale_manager_data.salePot
-  );
-  try {
-    await program.rpc.buyNft({
-      accounts: {
-        owner: owner.publicKey,
-        pool: pool,
-        nftMint: nft_mint,
-        metadata: metadata,
-        saleManager: sale_manager,
-        salePot: sale_manager_data.salePot,
-        nftManagerToken: sale_manager_data.nftPot,
-        nftBidderToken: nft_bidder_token,
-        managerPot: sale_pot_data.poolPot,
-        bidderToken: bidder_token,
-        tokenMetadataProgram: metadataProgramId,
-        tokenProgram: splToken.TOKEN_PROGRAM_ID,
-      },
-      signers: [owner],
-    });
-  } catch (err) {
-    console.log(err);
-  }
-  console.log("- end");
-  await sleep(100);
-  const account = await program.account.saleManager.fetch(sale_manager);
-  console.log(account);
+  let sale_pot_data = await program.account.salePot.fetch(sale_manager_data.salePot);
+  return await program.rpc.buyNft({
+    accounts: {
+      owner: owner.publicKey,
+      pool: pool,
+      config: config,
+      nftMint: nft_mint,
+      metadata: await findMetadata(nft_mint),
+      saleManager: sale_manager,
+      salePot: sale_manager_data.salePot,
+      nftManagerToken: sale_manager_data.nftPot,
+      nftBidderToken: nft_bidder_token,
+      managerPot: sale_pot_data.poolPot,
+      bidderToken: bidder_token,
+      // Only read when the pool charges a marketplace fee.
+      treasuryToken: treasury_token || bidder_token,
+      tokenMetadataProgram: metadataProgramId,
+      tokenProgram: splToken.TOKEN_PROGRAM_ID,
+    },
+    signers: [owner],
+  });
+}
+
+// Returns the sale pot, which the seller needs to withdraw the proceeds.
+export async function sellNftNative(
+  conn: Connection,
+  owner: Keypair,
+  pool: PublicKey,
+  nft_mint: PublicKey,
+  nft_seller_token: PublicKey,
+  nft_manager_token: PublicKey,
+  price: number
+) {
+  console.log("+ sellNftNative");
+  const program = loadProgram(conn, owner);
+  let [config] = await findConfig();
+  let [sale_manager] = await findSaleManager(pool, nft_mint);
+  let [metadata_extended] = await findMetadataExtended(nft_mint, pool);
+  let sale_pot = Keypair.generate();
+  await program.rpc.sellNftNative(new anchor.BN(price), {
+    accounts: {
+      owner: owner.publicKey,
+      pool: pool,
+      config: config,
+      nftMint: nft_mint,
+      metadata: await findMetadata(nft_mint),
+      metadataExtended: metadata_extended,
+      saleManager: sale_manager,
+      salePot: sale_pot.publicKey,
+      nftSellerToken: nft_seller_token,
+      nftManagerToken: nft_manager_token,
+      tokenMetadataProgram: metadataProgramId,
+      tokenProgram: splToken.TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    },
+    signers: [owner, sale_pot],
+  });
+  return sale_pot.publicKey;
+}
+
+export async function buyNftNative(
+  conn: Connection,
+  owner: Keypair,
+  pool: PublicKey,
+  nft_mint: PublicKey,
+  nft_bidder_token: PublicKey
+) {
+  console.log("+ buyNftNative");
+  const program = loadProgram(conn, owner);
+  let [config] = await findConfig();
+  let [sale_manager] = await findSaleManager(pool, nft_mint);
+  let sale_manager_data = await program.account.saleManager.fetch(sale_manager);
+  let pool_data = await program.account.pool.fetch(pool);
+  return await program.rpc.buyNftNative({
+    accounts: {
+      owner: owner.publicKey,
+      pool: pool,
+      config: config,
+      nftMint: nft_mint,
+      metadata: await findMetadata(nft_mint),
+      saleManager: sale_manager,
+      salePot: sale_manager_data.salePot,
+      nftManagerToken: sale_manager_data.nftPot,
+      nftBidderToken: nft_bidder_token,
+      feeTreasury: pool_data.feeTreasury,
+      tokenMetadataProgram: metadataProgramId,
+      tokenProgram: splToken.TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    },
+    signers: [owner],
+  });
+}
+
+// Legacy sale managers and sale pots have no version byte; these are the
+// offsets of the fields redeemNft and withdrawFund read from them.
+const LEGACY_NFT_POT_OFFSET = 8 + 32 * 3;
+const LEGACY_SALE_MANAGER_OFFSET = 8 + 1;
+const LEGACY_POOL_POT_OFFSET = 8 + 1 + 32;
+
+async function legacyKey(conn: Connection, address: PublicKey, offset: number) {
+  let info = await conn.getAccountInfo(address);
+  return new PublicKey(info!.data.slice(offset, offset + 32));
 }
 
+// `legacy` redeems a listing made before `pool` was migrated.
 export async function redeemNft(
   conn: Connection,
   owner: Keypair,
   pool: PublicKey,
   nft_mint: PublicKey,
-  nft_seller_token: PublicKey
+  nft_seller_token: PublicKey,
+  legacy: boolean = false
 ) {
   console.log("+ redeemNft");
-  let wallet = new anchor.Wallet(owner);
-  let provider = new anchor.Provider(
-    conn,
-    wallet,
-    anchor.Provider.defaultOptions()
-  );
-  const program = new anchor.Program(idl, programId, provider);
-  let sale_manager = (
-    await PublicKey.findProgramAddress(
-      [pool.toBuffer(), nft_mint.toBuffer()],
-      programId
-    )
-  )[0];
-  let metadata = (
-    await PublicKey.findProgramAddress(
-      [
-        Buffer.from("metadata"),
-        metadataProgramId.toBuffer(),
-        nft_mint.toBuffer(),
-      ],
-      metadataProgramId
-    )
-  )[0];
-  let sale_manager_data = await program.account.saleManager.fetch(sale_manager);
-  try {
-    await program.rpc.redeemNft({
-      accounts: {
-        owner: owner.publicKey,
-        pool: pool,
-        nftMint: nft_mint,
-        metadata: metadata,
-        saleManager: sale_manager,
-        nftSellerToken: nft_seller_token,
-        nftManagerToken: sale_manager_data.nftPot,
-        tokenMetadataProgram: metadataProgramId,
-        tokenProgram: splToken.TOKEN_PROGRAM_ID,
-      },
-      signers: [owner],
-    });
-  } catch (err) {
-    console.log(err);
-  }
-  console.log("- end");
-  await sleep(100);
-  // const account = await program.account.saleManager.fetch(sale_manager)
-  // console.log(account)
+  const program = loadProgram(conn, owner);
+  let listing_pool = legacy ? (await program.account.pool.fetch(pool)).legacyPool : pool;
+  let [sale_manager] = await findSaleManager(listing_pool, nft_mint);
+  let nft_pot = legacy
+    ? await legacyKey(conn, sale_manager, LEGACY_NFT_POT_OFFSET)
+    : (await program.account.saleManager.fetch(sale_manager)).nftPot;
+  return await program.rpc.redeemNft({
+    accounts: {
+      owner: owner.publicKey,
+      pool: pool,
+      nftMint: nft_mint,
+      metadata: await findMetadata(nft_mint),
+      saleManager: sale_manager,
+      nftSellerToken: nft_seller_token,
+      nftManagerToken: nft_pot,
+      tokenMetadataProgram: metadataProgramId,
+      tokenProgram: splToken.TOKEN_PROGRAM_ID,
+    },
+    signers: [owner],
+  });
 }
 
+// `legacy` withdraws from a pot sold before `pool` was migrated.
 export async function withdrawFund(
   conn: Connection,
   owner: Keypair,
-  sale_manager: PublicKey,
-  withraw_pot: PublicKey
+  pool: PublicKey,
+  sale_pot: PublicKey,
+  withraw_pot: PublicKey,
+  legacy: boolean = false
 ) {
   console.log("+ withdrawFund");
-  let wallet = new anchor.Wallet(owner);
-  let provider = new anchor.Provider(
-    conn,
-    wallet,
-    anchor.Provider.defaultOptions()
-  );
-  const program = new anchor.Program(idl, programId, provider);
-  let sale_manager_data = await program.account.saleManager.fetch(sale_manager);
-  let sale_pot_data = await program.account.salePot.fetch(
-    sale_manager_data.salePot
-  );
-  try {
-    await program.rpc.withdrawFund({
-      accounts: {
-        owner: owner.publicKey,
-        saleManager: sale_pot_data.saleManager,
-        salePot: sale_manager_data.salePot,
-        poolPot: sale_pot_data.poolPot,
-        withdrawPot: withraw_pot,
-        tokenProgram: splToken.TOKEN_PROGRAM_ID,
-      },
-      signers: [owner],
-    });
-  } catch (err) {
-    console.log(err);
-  }
-  console.log("- end");
-  await sleep(100);
-  // const account = await program.account.saleManager.fetch(sale_manager)
-  // console.log(account)
+  const program = loadProgram(conn, owner);
+  let [config] = await findConfig();
+  let sale_pot_data = legacy
+    ? {
+        saleManager: await legacyKey(conn, sale_pot, LEGACY_SALE_MANAGER_OFFSET),
+        poolPot: await legacyKey(conn, sale_pot, LEGACY_POOL_POT_OFFSET),
+      }
+    : await program.account.salePot.fetch(sale_pot);
+  return await program.rpc.withdrawFund({
+    accounts: {
+      owner: owner.publicKey,
+      pool: pool,
+      config: config,
+      saleManager: sale_pot_data.saleManager,
+      salePot: sale_pot,
+      poolPot: sale_pot_data.poolPot,
+      withdrawPot: withraw_pot,
+      tokenProgram: splToken.TOKEN_PROGRAM_ID,
+    },
+    signers: [owner],
+  });
+}
+
+export async function withdrawFundNative(
+  conn: Connection,
+  owner: Keypair,
+  pool: PublicKey,
+  sale_pot: PublicKey
+) {
+  console.log("+ withdrawFundNative");
+  const program = loadProgram(conn, owner);
+  let [config] = await findConfig();
+  let sale_pot_data = await program.account.salePot.fetch(sale_pot);
+  return await program.rpc.withdrawFundNative({
+    accounts: {
+      owner: owner.publicKey,
+      pool: pool,
+      config: config,
+      saleManager: sale_pot_data.saleManager,
+      salePot: sale_pot,
+      rent: SYSVAR_RENT_PUBKEY,
+    },
+    signers: [owner],
+  });
+}
+
+// Needs the pool owner while the pool is open; anyone may close it afterwards.
+export async function closeMetadataExtended(
+  conn: Connection,
+  authority: Keypair,
+  pool: PublicKey,
+  mint: PublicKey
+) {
+  console.log("+ closeMetadataExtended");
+  const program = loadProgram(conn, authority);
+  let [metadataExtended] = await findMetadataExtended(mint, pool);
+  let [saleManager] = await findSaleManager(pool, mint);
+  let data = await program.account.metadataExtended.fetch(metadataExtended);
+  return await program.rpc.closeMetadataExtended({
+    accounts: {
+      authority: authority.publicKey,
+      pool: pool,
+      mint: mint,
+      metadataExtended: metadataExtended,
+      saleManager: saleManager,
+      minter: data.minter,
+    },
+    signers: [authority],
+  });
+}
+
+export async function closeSaleManager(conn: Connection, owner: Keypair, pool: PublicKey, mint: PublicKey) {
+  console.log("+ closeSaleManager");
+  const program = loadProgram(conn, owner);
+  let [saleManager] = await findSaleManager(pool, mint);
+  let data = await program.account.saleManager.fetch(saleManager);
+  return await program.rpc.closeSaleManager({
+    accounts: {
+      owner: owner.publicKey,
+      pool: pool,
+      nftMint: mint,
+      saleManager: saleManager,
+      salePot: data.salePot,
+      recipient: owner.publicKey,
+      rent: SYSVAR_RENT_PUBKEY,
+    },
+    signers: [owner],
+  });
+}
+
+/* Migrations */
+
+// Closes the legacy pool; its lamports go to `payer`.
+export async function migratePool(conn: Connection, payer: Keypair, legacyPool: PublicKey) {
+  console.log("+ migratePool");
+  const program = loadProgram(conn, payer);
+  let [pool, bump] = await findMigratedPool(legacyPool);
+  return await program.rpc.migratePool(bump, {
+    accounts: {
+      payer: payer.publicKey,
+      legacyPool: legacyPool,
+      pool: pool,
+      systemProgram: SystemProgram.programId,
+    },
+    signers: [payer],
+  });
+}
+
+// `pool` is the migrated pool; `legacyClient` is the client `bidder` had on the
+// pool it was migrated from.
+export async function migrateClient(
+  conn: Connection,
+  payer: Keypair,
+  pool: PublicKey,
+  legacyClient: PublicKey,
+  bidder: PublicKey
+) {
+  console.log("+ migrateClient");
+  const program = loadProgram(conn, payer);
+  let [client, bump] = await findClient(pool, bidder);
+  return await program.rpc.migrateClient(bump, {
+    accounts: {
+      payer: payer.publicKey,
+      pool: pool,
+      bidder: bidder,
+      legacyClient: legacyClient,
+      client: client,
+      systemProgram: SystemProgram.programId,
+    },
+    signers: [payer],
+  });
+}
+
+export async function migrateMetadataExtended(
+  conn: Connection,
+  payer: Keypair,
+  pool: PublicKey,
+  legacyMetadataExtended: PublicKey,
+  mint: PublicKey
+) {
+  console.log("+ migrateMetadataExtended");
+  const program = loadProgram(conn, payer);
+  let [metadataExtended, bump] = await findMetadataExtended(mint, pool);
+  return await program.rpc.migrateMetadataExtended(bump, {
+    accounts: {
+      payer: payer.publicKey,
+      pool: pool,
+      mint: mint,
+      legacyMetadataExtended: legacyMetadataExtended,
+      metadataExtended: metadataExtended,
+      systemProgram: SystemProgram.programId,
+    },
+    signers: [payer],
+  });
 }
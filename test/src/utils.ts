@@ -1,11 +1,17 @@
 import { PublicKey } from "@solana/web3.js";
+import * as anchor from "@project-serum/anchor";
 
+import * as crypto from "crypto";
 import * as fs from "fs";
 
 export const logError = (msg: string) => {
   console.log(`\x1b[31m${msg}\x1b[0m`);
 };
 
+export const logSuccess = (msg: string) => {
+  console.log(`\x1b[32m${msg}\x1b[0m`);
+};
+
 export const getPublicKey = (name: string) => {
   return new PublicKey(
     JSON.parse(fs.readFileSync(`keys/${name}_pub.json`) as unknown as string)
@@ -17,6 +23,9 @@ export const getPrivateKey = (name: string) =>
     JSON.parse(fs.readFileSync(`./keys/${name}.json`) as unknown as string)
   );
 
+export const hasPrivateKey = (name: string) =>
+  fs.existsSync(`./keys/${name}.json`);
+
 export const getProgramId = (name: string) => {
   try {
     return getPublicKey(name);
@@ -25,3 +34,117 @@ export const getProgramId = (name: string) => {
     process.exit(1);
   }
 };
+
+const idl = JSON.parse(fs.readFileSync("src/solana_anchor.json", "utf8"));
+
+export const errorCode = (name: string): number => {
+  const error = idl.errors.find((e: any) => e.name === name);
+  if (!error) {
+    throw new Error(`Unknown program error ${name}`);
+  }
+  return error.code;
+};
+
+let failures = 0;
+
+export const failureCount = () => failures;
+
+// Runs one step of the scenario and records whether it succeeded.
+export async function expectOk<T>(label: string, action: () => Promise<T>) {
+  try {
+    const result = await action();
+    logSuccess(`ok   ${label}`);
+    return result;
+  } catch (err) {
+    failures++;
+    logError(`FAIL ${label}`);
+    console.log(err);
+    return undefined;
+  }
+}
+
+// Runs one step that the program must reject. With `error` set, only that
+// program error counts as the expected rejection.
+export async function expectError(
+  label: string,
+  action: () => Promise<any>,
+  error?: string
+) {
+  try {
+    await action();
+  } catch (err: any) {
+    if (error === undefined || err.code === errorCode(error)) {
+      logSuccess(`ok   ${label} (rejected${error ? ": " + error : ""})`);
+      return;
+    }
+    failures++;
+    logError(`FAIL ${label}: expected ${error}, got`);
+    console.log(err);
+    return;
+  }
+  failures++;
+  logError(`FAIL ${label}: expected a rejection${error ? " with " + error : ""}`);
+}
+
+export const sha256 = (...parts: Buffer[]) =>
+  crypto.createHash("sha256").update(Buffer.concat(parts)).digest();
+
+export const u64 = (value: number) =>
+  new anchor.BN(value).toArrayLike(Buffer, "le", 8);
+
+const borshString = (value: string) => {
+  const bytes = Buffer.from(value, "utf8");
+  const len = Buffer.alloc(4);
+  len.writeUInt32LE(bytes.length);
+  return Buffer.concat([len, bytes]);
+};
+
+// Leaf of the merkle_root allowlist, as hashed by mint_nft_with_proof.
+export const allowlistLeaf = (
+  wallet: PublicKey,
+  allocation: number,
+  tier: number
+) => sha256(wallet.toBuffer(), u64(allocation), Buffer.from([tier]));
+
+// Leaf of the reveal_root, as hashed by reveal.
+export const revealLeaf = (
+  mintIndex: number,
+  line: { name: string; uri: string }
+) => sha256(u64(mintIndex), borshString(line.name), borshString(line.uri));
+
+// The program hashes each pair in sorted order, so a proof is just the list of
+// siblings from the leaf up.
+const hashPair = (a: Buffer, b: Buffer) =>
+  Buffer.compare(a, b) <= 0 ? sha256(a, b) : sha256(b, a);
+
+export class MerkleTree {
+  layers: Buffer[][];
+
+  constructor(leaves: Buffer[]) {
+    this.layers = [leaves];
+    while (this.layers[this.layers.length - 1].length > 1) {
+      const layer = this.layers[this.layers.length - 1];
+      const next: Buffer[] = [];
+      for (let i = 0; i < layer.length; i += 2) {
+        next.push(i + 1 < layer.length ? hashPair(layer[i], layer[i + 1]) : layer[i]);
+      }
+      this.layers.push(next);
+    }
+  }
+
+  root(): number[] {
+    return Array.from(this.layers[this.layers.length - 1][0]);
+  }
+
+  proof(index: number): number[][] {
+    const proof: number[][] = [];
+    for (let l = 0; l < this.layers.length - 1; l++) {
+      const sibling = index ^ 1;
+      if (sibling < this.layers[l].length) {
+        proof.push(Array.from(this.layers[l][sibling]));
+      }
+      index = Math.floor(index / 2);
+    }
+    return proof;
+  }
+}